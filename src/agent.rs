@@ -0,0 +1,139 @@
+use crate::service_group_lib::{ServiceGroupLib};
+use crate::socket::{WebSocket, ProtocolKind};
+use crate::http_api_wrapper::{HTTPAPIWrapper};
+use crate::http_api::{HttpApi};
+use crate::outbound::{OutboundQueue};
+use crate::outbox::{Outbox};
+use crate::types::{Config, MessageContent};
+
+use serde_json::Value;
+use failure::Error;
+
+/// A message received by an `Agent`.
+#[derive(Debug, Clone)]
+pub enum AgentEvent {
+    MessageDown {
+        channel_id: String,
+        sender: String,
+        content: Option<MessageContent>,
+        body: Value,
+    },
+    Update(Value),
+    Other(Value),
+}
+
+/// A user-side client: logs in as a user character, joins channels and talks to
+/// the services running them through `message_up` and `button_click`.
+pub struct Agent {
+    pub config: Config,
+    pub http_client: Box<dyn HttpApi>,
+    pub ws_client: WebSocket<ProtocolKind>,
+    pub service_group_lib: ServiceGroupLib,
+    pub user_id: Option<String>,
+}
+
+impl Agent {
+    pub async fn new(config: Config) -> Result<Self, Error> {
+        let http_client: Box<dyn HttpApi> = Box::new(HTTPAPIWrapper::new(config.clone()));
+        let ws_client = WebSocket::connect(config.protocol, &config.ws_server_uri).await?;
+        let ws_client = match &config.outbound {
+            Some(outbound) => ws_client.with_outbound_queue(OutboundQueue::from_config(outbound)?),
+            None => ws_client,
+        };
+        let ws_client = match &config.outbox {
+            Some(outbox) => ws_client.with_outbox(Outbox::from_config(outbox)?),
+            None => ws_client,
+        };
+        let service_group_lib = ServiceGroupLib::new();
+        Ok(Self {
+            config,
+            http_client,
+            ws_client,
+            service_group_lib,
+            user_id: None,
+        })
+    }
+
+    /// Replaces the HTTP API client, e.g. with a `FakeHttpApi` in tests.
+    pub fn with_http_api(mut self, http_api: impl HttpApi + 'static) -> Self {
+        self.http_client = Box::new(http_api);
+        self
+    }
+
+    /// Authenticates over HTTP, looks up the user's ID and sends `user_login`.
+    pub async fn login(&mut self) -> Result<String, Box<dyn std::error::Error>> {
+        let (access_token, _refresh_token) = self.http_client.authenticate()?;
+        let user_info = self.http_client.fetch_user_info()?;
+        let user_id = user_info["user_id"].as_str().ok_or("user_id not found in user info")?.to_string();
+        self.ws_client.user_login(&access_token).await?;
+        self.ws_client.flush().await?;
+        self.user_id = Some(user_id.clone());
+        Ok(user_id)
+    }
+
+    fn user_id(&self) -> Result<String, Box<dyn std::error::Error>> {
+        self.user_id.clone().ok_or_else(|| "Agent is not logged in".into())
+    }
+
+    pub async fn join_channel(&mut self, channel_id: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let user_id = self.user_id()?;
+        self.ws_client.join_channel(&user_id, channel_id).await?;
+        self.ws_client.flush().await?;
+        Ok(())
+    }
+
+    pub async fn leave_channel(&mut self, channel_id: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let user_id = self.user_id()?;
+        self.ws_client.leave_channel(&user_id, channel_id).await?;
+        self.ws_client.flush().await?;
+        Ok(())
+    }
+
+    /// Sends `content` to `recipients` in `channel_id` through `message_up`.
+    pub async fn send_message(
+        &mut self,
+        content: &MessageContent,
+        channel_id: &str,
+        recipients: Vec<String>,
+    ) -> Result<Value, Box<dyn std::error::Error>> {
+        let user_id = self.user_id()?;
+        let service_id = self.config.service_id.clone().unwrap_or_default();
+        let group_recipients = self.service_group_lib.convert_list(self.http_client.as_ref(), recipients, false, Some(channel_id.to_string())).await?;
+        let message = self.ws_client.message_up(&user_id, &service_id, channel_id, &group_recipients, content).await?;
+        self.ws_client.flush().await?;
+        Ok(message)
+    }
+
+    pub async fn click_button(
+        &mut self,
+        channel_id: &str,
+        button_id: &str,
+        arguments: &[(String, String)],
+    ) -> Result<Value, Box<dyn std::error::Error>> {
+        let user_id = self.user_id()?;
+        let message = self.ws_client.button_click(&user_id, channel_id, button_id, arguments).await?;
+        self.ws_client.flush().await?;
+        Ok(message)
+    }
+
+    /// Waits for the next message from the server.
+    pub async fn next_event(&mut self) -> Result<AgentEvent, Box<dyn std::error::Error>> {
+        // Don't leave rate-limited messages queued while waiting for the server.
+        self.ws_client.flush().await?;
+        let payload = self.ws_client.recv::<Value>().await?;
+        let event = match payload["type"].as_str() {
+            Some("message_down") => {
+                let body = payload["body"].clone();
+                AgentEvent::MessageDown {
+                    channel_id: body["channel_id"].as_str().unwrap_or_default().to_string(),
+                    sender: body["sender"].as_str().unwrap_or_default().to_string(),
+                    content: MessageContent::from_body(body["subtype"].as_str().unwrap_or_default(), &body["content"]),
+                    body,
+                }
+            }
+            Some("update") => AgentEvent::Update(payload["body"].clone()),
+            _ => AgentEvent::Other(payload),
+        };
+        Ok(event)
+    }
+}
@@ -0,0 +1,228 @@
+use crate::buttons::{Button};
+
+use std::collections::{HashMap, HashSet};
+
+/// An incremental change to the buttons a user sees.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ButtonChange {
+    /// Add a button, or replace the one with the same `button_id`.
+    Add(Button),
+    Show(String),
+    Hide(String),
+    ShowAll,
+    HideAll,
+    Disable(String),
+    Enable(String),
+    Relabel { button_id: String, button_text: String },
+}
+
+impl ButtonChange {
+    /// Whether applying `self` after `earlier` makes `earlier` irrelevant.
+    fn supersedes(&self, earlier: &ButtonChange) -> bool {
+        use ButtonChange::*;
+        match (self, earlier) {
+            (ShowAll | HideAll, Show(_) | Hide(_) | ShowAll | HideAll) => true,
+            (Show(id) | Hide(id), Show(other) | Hide(other)) => id == other,
+            (Disable(id) | Enable(id), Disable(other) | Enable(other)) => id == other,
+            (Relabel { button_id: id, .. }, Relabel { button_id: other, .. }) => id == other,
+            // An added button replaces everything about the one it replaces.
+            (Add(button), Add(other)) => button.button_id == other.button_id,
+            (Add(button), Show(other) | Hide(other) | Disable(other) | Enable(other) | Relabel { button_id: other, .. }) => &button.button_id == other,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct UserButtons {
+    /// The buttons from the button source this state was built on.
+    base: Vec<Button>,
+    /// The changes applied since that still matter, so they can be replayed on a new
+    /// base. Changes overridden by later ones are dropped.
+    changes: Vec<ButtonChange>,
+    buttons: Vec<Button>,
+    hidden: HashSet<String>,
+}
+
+impl UserButtons {
+    fn new(base: Vec<Button>) -> Self {
+        UserButtons { buttons: base.clone(), base, changes: Vec::new(), hidden: HashSet::new() }
+    }
+
+    fn record(&mut self, change: &ButtonChange) {
+        self.apply(change);
+        self.changes.retain(|earlier| !change.supersedes(earlier));
+        self.changes.push(change.clone());
+    }
+
+    /// Rebuilds the state on `base`, keeping the user's changes.
+    fn rebase(&mut self, base: Vec<Button>) {
+        let changes = std::mem::take(&mut self.changes);
+        *self = UserButtons::new(base);
+        for change in &changes {
+            self.apply(change);
+        }
+        self.changes = changes;
+    }
+
+    fn apply(&mut self, change: &ButtonChange) {
+        match change {
+            ButtonChange::Add(button) => {
+                match self.buttons.iter_mut().find(|b| b.button_id == button.button_id) {
+                    Some(existing) => *existing = button.clone(),
+                    None => self.buttons.push(button.clone()),
+                }
+                self.hidden.remove(&button.button_id);
+            }
+            ButtonChange::Show(button_id) => {
+                self.hidden.remove(button_id);
+            }
+            ButtonChange::Hide(button_id) => {
+                self.hidden.insert(button_id.clone());
+            }
+            ButtonChange::ShowAll => self.hidden.clear(),
+            ButtonChange::HideAll => self.hidden = self.buttons.iter().map(|b| b.button_id.clone()).collect(),
+            ButtonChange::Disable(button_id) | ButtonChange::Enable(button_id) => {
+                let disabled = matches!(change, ButtonChange::Disable(_));
+                if let Some(button) = self.buttons.iter_mut().find(|b| &b.button_id == button_id) {
+                    button.disabled = disabled;
+                }
+            }
+            ButtonChange::Relabel { button_id, button_text } => {
+                if let Some(button) = self.buttons.iter_mut().find(|b| &b.button_id == button_id) {
+                    button.button_text = button_text.clone();
+                }
+            }
+        }
+    }
+
+    fn visible(&self) -> Vec<Button> {
+        self.buttons.iter().filter(|b| !self.hidden.contains(&b.button_id)).cloned().collect()
+    }
+}
+
+/// Tracks which buttons each user currently sees, per channel.
+#[derive(Debug, Default)]
+pub struct ButtonState {
+    users: HashMap<(String, String), UserButtons>,
+}
+
+impl ButtonState {
+    pub fn new() -> Self {
+        ButtonState {
+            users: HashMap::new(),
+        }
+    }
+
+    pub fn is_tracked(&self, channel_id: &str, user_id: &str) -> bool {
+        self.users.contains_key(&(channel_id.to_string(), user_id.to_string()))
+    }
+
+    /// Starts tracking a user with `buttons` all shown, replacing any previous state.
+    pub fn init(&mut self, channel_id: &str, user_id: &str, buttons: Vec<Button>) {
+        self.users.insert((channel_id.to_string(), user_id.to_string()), UserButtons::new(buttons));
+    }
+
+    /// Brings a user up to date with the button source: starts tracking them on
+    /// `buttons`, or, if the source has changed since, replays their changes on it.
+    pub fn sync(&mut self, channel_id: &str, user_id: &str, buttons: Vec<Button>) {
+        match self.users.get_mut(&(channel_id.to_string(), user_id.to_string())) {
+            Some(state) if state.base != buttons => state.rebase(buttons),
+            Some(_) => {}
+            None => self.init(channel_id, user_id, buttons),
+        }
+    }
+
+    /// Applies `changes` to a tracked user and returns their new visible buttons,
+    /// or `None` if the user is not tracked.
+    pub fn apply(&mut self, channel_id: &str, user_id: &str, changes: &[ButtonChange]) -> Option<Vec<Button>> {
+        let state = self.users.get_mut(&(channel_id.to_string(), user_id.to_string()))?;
+        for change in changes {
+            state.record(change);
+        }
+        Some(state.visible())
+    }
+
+    pub fn visible(&self, channel_id: &str, user_id: &str) -> Option<Vec<Button>> {
+        self.users.get(&(channel_id.to_string(), user_id.to_string())).map(UserButtons::visible)
+    }
+
+    /// Forgets a user's state so the next `fetch_buttons` starts over from the button source.
+    pub fn reset(&mut self, channel_id: &str, user_id: &str) {
+        self.users.remove(&(channel_id.to_string(), user_id.to_string()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buttons(ids: &[&str]) -> Vec<Button> {
+        ids.iter().map(|id| Button::new(*id, *id, *id)).collect()
+    }
+
+    fn visible_ids(state: &ButtonState) -> Vec<String> {
+        state.visible("channel", "user").unwrap().into_iter().map(|b| b.button_id).collect()
+    }
+
+    #[test]
+    fn hidden_button_stays_hidden_when_the_source_changes() {
+        let mut state = ButtonState::new();
+        state.sync("channel", "user", buttons(&["a", "b"]));
+        state.apply("channel", "user", &[ButtonChange::Hide("a".to_string())]);
+        state.sync("channel", "user", buttons(&["a", "b", "c"]));
+        assert_eq!(visible_ids(&state), vec!["b", "c"]);
+    }
+
+    #[test]
+    fn hide_all_and_show_all() {
+        let mut state = ButtonState::new();
+        state.sync("channel", "user", buttons(&["a", "b"]));
+        assert_eq!(state.apply("channel", "user", &[ButtonChange::HideAll]), Some(vec![]));
+        state.sync("channel", "user", buttons(&["a", "b", "c"]));
+        assert!(visible_ids(&state).is_empty());
+        state.apply("channel", "user", &[ButtonChange::ShowAll, ButtonChange::Hide("b".to_string())]);
+        assert_eq!(visible_ids(&state), vec!["a", "c"]);
+    }
+
+    #[test]
+    fn relabel_survives_a_rebase() {
+        let mut state = ButtonState::new();
+        state.sync("channel", "user", buttons(&["a"]));
+        state.apply("channel", "user", &[ButtonChange::Relabel { button_id: "a".to_string(), button_text: "New".to_string() }]);
+        state.sync("channel", "user", buttons(&["a", "b"]));
+        let visible = state.visible("channel", "user").unwrap();
+        assert_eq!(visible[0].button_text, "New");
+        assert_eq!(visible[1].button_text, "b");
+    }
+
+    #[test]
+    fn add_replaces_a_button_with_the_same_id() {
+        let mut state = ButtonState::new();
+        state.sync("channel", "user", buttons(&["a", "b"]));
+        let replacement = Button::new("a", "a", "Replaced").disabled(true);
+        state.apply("channel", "user", &[ButtonChange::Hide("a".to_string()), ButtonChange::Add(replacement.clone())]);
+        state.sync("channel", "user", buttons(&["a", "b", "c"]));
+        let visible = state.visible("channel", "user").unwrap();
+        assert_eq!(visible.len(), 3);
+        assert_eq!(visible[0], replacement);
+    }
+
+    #[test]
+    fn overridden_changes_are_not_kept() {
+        let mut state = ButtonState::new();
+        state.sync("channel", "user", buttons(&["a", "b"]));
+        for _ in 0..50 {
+            state.apply("channel", "user", &[
+                ButtonChange::Hide("a".to_string()),
+                ButtonChange::Show("a".to_string()),
+                ButtonChange::Disable("b".to_string()),
+                ButtonChange::Enable("b".to_string()),
+                ButtonChange::HideAll,
+                ButtonChange::ShowAll,
+            ]);
+        }
+        let user = &state.users[&("channel".to_string(), "user".to_string())];
+        assert_eq!(user.changes, vec![ButtonChange::Enable("b".to_string()), ButtonChange::ShowAll]);
+    }
+}
@@ -0,0 +1,417 @@
+use serde_derive::{Serialize, Deserialize};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::time::SystemTime;
+
+/// A button shown to users through `update_buttons`.
+/// Serializes to the same JSON format as `buttons.json`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Button {
+    pub button_id: String,
+    pub button_name: String,
+    pub button_text: String,
+    #[serde(default)]
+    pub new_window: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub arguments: Vec<ButtonArgument>,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub disabled: bool,
+}
+
+fn is_false(b: &bool) -> bool {
+    !*b
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ArgumentType {
+    Enum,
+    Text,
+    Number,
+    Boolean,
+}
+
+/// An input the user fills in before a `new_window` button click is sent.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ButtonArgument {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub arg_type: ArgumentType,
+    #[serde(default)]
+    pub optional: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub values: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub placeholder: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum ButtonError {
+    DuplicateButtonId(String),
+    EmptyField { button_id: String, field: &'static str },
+    DuplicateArgument { button_id: String, argument: String },
+    MissingEnumValues { button_id: String, argument: String },
+    Io(std::io::Error),
+    Parse(serde_json::Error),
+}
+
+impl fmt::Display for ButtonError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ButtonError::DuplicateButtonId(id) => write!(f, "duplicate button_id {:?}", id),
+            ButtonError::EmptyField { button_id, field } => write!(f, "button {:?} has an empty {}", button_id, field),
+            ButtonError::DuplicateArgument { button_id, argument } => write!(f, "button {:?} declares argument {:?} twice", button_id, argument),
+            ButtonError::MissingEnumValues { button_id, argument } => write!(f, "enum argument {:?} of button {:?} has no values", argument, button_id),
+            ButtonError::Io(e) => write!(f, "failed to read buttons: {}", e),
+            ButtonError::Parse(e) => write!(f, "failed to parse buttons: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ButtonError {}
+
+impl From<std::io::Error> for ButtonError {
+    fn from(e: std::io::Error) -> Self {
+        ButtonError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for ButtonError {
+    fn from(e: serde_json::Error) -> Self {
+        ButtonError::Parse(e)
+    }
+}
+
+impl Button {
+    pub fn new(button_id: impl Into<String>, button_name: impl Into<String>, button_text: impl Into<String>) -> Self {
+        Button {
+            button_id: button_id.into(),
+            button_name: button_name.into(),
+            button_text: button_text.into(),
+            new_window: false,
+            arguments: Vec::new(),
+            disabled: false,
+        }
+    }
+
+    pub fn new_window(mut self, new_window: bool) -> Self {
+        self.new_window = new_window;
+        self
+    }
+
+    pub fn disabled(mut self, disabled: bool) -> Self {
+        self.disabled = disabled;
+        self
+    }
+
+    /// Adds an argument. Buttons with arguments open a new window to collect them.
+    pub fn argument(mut self, argument: ButtonArgument) -> Self {
+        self.arguments.push(argument);
+        self.new_window = true;
+        self
+    }
+
+    pub fn argument_named(&self, name: &str) -> Option<&ButtonArgument> {
+        self.arguments.iter().find(|a| a.name == name)
+    }
+
+    pub fn validate(&self) -> Result<(), ButtonError> {
+        let empty = |field| ButtonError::EmptyField { button_id: self.button_id.clone(), field };
+        if self.button_id.is_empty() {
+            return Err(empty("button_id"));
+        }
+        if self.button_name.is_empty() {
+            return Err(empty("button_name"));
+        }
+        if self.button_text.is_empty() {
+            return Err(empty("button_text"));
+        }
+        let mut names = HashSet::new();
+        for argument in &self.arguments {
+            if argument.name.is_empty() {
+                return Err(empty("argument name"));
+            }
+            if !names.insert(argument.name.as_str()) {
+                return Err(ButtonError::DuplicateArgument { button_id: self.button_id.clone(), argument: argument.name.clone() });
+            }
+            if argument.arg_type == ArgumentType::Enum && argument.values.is_empty() {
+                return Err(ButtonError::MissingEnumValues { button_id: self.button_id.clone(), argument: argument.name.clone() });
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ButtonArgument {
+    pub fn new(name: impl Into<String>, arg_type: ArgumentType) -> Self {
+        ButtonArgument {
+            name: name.into(),
+            arg_type,
+            optional: false,
+            values: Vec::new(),
+            placeholder: None,
+        }
+    }
+
+    pub fn enumeration<S: Into<String>>(name: impl Into<String>, values: impl IntoIterator<Item = S>) -> Self {
+        let mut argument = ButtonArgument::new(name, ArgumentType::Enum);
+        argument.values = values.into_iter().map(Into::into).collect();
+        argument
+    }
+
+    pub fn text(name: impl Into<String>) -> Self {
+        ButtonArgument::new(name, ArgumentType::Text)
+    }
+
+    pub fn number(name: impl Into<String>) -> Self {
+        ButtonArgument::new(name, ArgumentType::Number)
+    }
+
+    pub fn boolean(name: impl Into<String>) -> Self {
+        ButtonArgument::new(name, ArgumentType::Boolean)
+    }
+
+    pub fn optional(mut self, optional: bool) -> Self {
+        self.optional = optional;
+        self
+    }
+
+    pub fn placeholder(mut self, placeholder: impl Into<String>) -> Self {
+        self.placeholder = Some(placeholder.into());
+        self
+    }
+}
+
+/// Checks every button and that no two buttons share a `button_id`.
+pub fn validate_buttons(buttons: &[Button]) -> Result<(), ButtonError> {
+    let mut ids = HashSet::new();
+    for button in buttons {
+        button.validate()?;
+        if !ids.insert(button.button_id.as_str()) {
+            return Err(ButtonError::DuplicateButtonId(button.button_id.clone()));
+        }
+    }
+    Ok(())
+}
+
+/// Parses and validates a JSON list of buttons.
+pub fn parse_buttons(json: &str) -> Result<Vec<Button>, ButtonError> {
+    let buttons: Vec<Button> = serde_json::from_str(json)?;
+    validate_buttons(&buttons)?;
+    Ok(buttons)
+}
+
+pub fn load_buttons(path: &str) -> Result<Vec<Button>, ButtonError> {
+    let json = std::fs::read_to_string(path)?;
+    parse_buttons(&json)
+}
+
+/// Returns the buttons for a `(channel_id, user_id)` pair.
+pub type ButtonProvider = Box<dyn Fn(&str, &str) -> Vec<Button> + Send + Sync>;
+
+/// Where `Moobius` gets the buttons it sends in reply to `fetch_buttons`.
+pub enum ButtonSource {
+    /// The same buttons for every channel and user.
+    Static(Vec<Button>),
+    /// A JSON file in the `buttons.json` format. With `hot_reload` the file is
+    /// re-read whenever its modification time changes.
+    File {
+        path: String,
+        hot_reload: bool,
+        cached: Option<(Option<SystemTime>, Vec<Button>)>,
+    },
+    /// A callback deciding per channel and per user.
+    Provider(ButtonProvider),
+}
+
+impl ButtonSource {
+    pub fn file(path: impl Into<String>, hot_reload: bool) -> Self {
+        ButtonSource::File { path: path.into(), hot_reload, cached: None }
+    }
+
+    pub fn provider(provider: impl Fn(&str, &str) -> Vec<Button> + Send + Sync + 'static) -> Self {
+        ButtonSource::Provider(Box::new(provider))
+    }
+
+    pub fn buttons_for(&mut self, channel_id: &str, user_id: &str) -> Result<Vec<Button>, ButtonError> {
+        match self {
+            ButtonSource::Static(buttons) => Ok(buttons.clone()),
+            ButtonSource::Provider(provider) => {
+                let buttons = provider(channel_id, user_id);
+                validate_buttons(&buttons)?;
+                Ok(buttons)
+            }
+            ButtonSource::File { path, hot_reload, cached } => {
+                let modified = std::fs::metadata(path.as_str()).and_then(|m| m.modified()).ok();
+                let stale = match cached {
+                    None => true,
+                    Some((loaded_at, _)) => *hot_reload && *loaded_at != modified,
+                };
+                if stale {
+                    match load_buttons(path) {
+                        Ok(buttons) => *cached = Some((modified, buttons)),
+                        // Keep serving the last good buttons if an edit broke the file.
+                        Err(e) if cached.is_some() => println!("Error reloading buttons from {}, keeping previous: {}", path, e),
+                        Err(e) => return Err(e),
+                    }
+                }
+                Ok(cached.as_ref().map(|(_, buttons)| buttons.clone()).unwrap_or_default())
+            }
+        }
+    }
+}
+
+impl Default for ButtonSource {
+    fn default() -> Self {
+        ButtonSource::Static(Vec::new())
+    }
+}
+
+/// An argument value from a button click, typed by the button's declared schema.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ArgValue {
+    Text(String),
+    Number(f64),
+    Boolean(bool),
+    /// One of the argument's declared `values`, in its declared spelling.
+    Enum(String),
+}
+
+impl ArgValue {
+    /// The value as a string, for `Text` and `Enum` arguments.
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            ArgValue::Text(s) | ArgValue::Enum(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            ArgValue::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            ArgValue::Boolean(b) => Some(*b),
+            _ => None,
+        }
+    }
+}
+
+/// Parses the `arguments` of a `button_click` body against `button`'s declared arguments.
+/// Without a declared schema every argument is taken as text.
+pub fn parse_arguments(button: Option<&Button>, arguments: &Value) -> Result<HashMap<String, ArgValue>, String> {
+    let mut parsed = HashMap::new();
+    for argument in arguments.as_array().map(|a| a.as_slice()).unwrap_or(&[]) {
+        let name = argument["name"].as_str().ok_or("argument without a name")?;
+        let raw = &argument["value"];
+        if raw.is_null() || raw.as_str() == Some("") {
+            continue;
+        }
+        let declared = match button {
+            Some(button) => Some(button.argument_named(name).ok_or_else(|| format!("unknown argument {:?}", name))?),
+            None => None,
+        };
+        let raw_str = raw.as_str().map(|s| s.to_string()).unwrap_or_else(|| raw.to_string());
+        let value = match declared.map(|d| d.arg_type) {
+            None | Some(ArgumentType::Text) => ArgValue::Text(raw_str),
+            Some(ArgumentType::Number) => ArgValue::Number(match raw.as_f64() {
+                Some(n) => n,
+                None => raw_str.trim().parse().map_err(|_| format!("{:?} must be a number, got {:?}", name, raw_str))?,
+            }),
+            Some(ArgumentType::Boolean) => ArgValue::Boolean(match raw.as_bool() {
+                Some(b) => b,
+                None => match raw_str.trim().to_lowercase().as_str() {
+                    "true" | "yes" | "1" => true,
+                    "false" | "no" | "0" => false,
+                    _ => return Err(format!("{:?} must be true or false, got {:?}", name, raw_str)),
+                },
+            }),
+            Some(ArgumentType::Enum) => {
+                let values = &declared.unwrap().values;
+                let matched = values.iter().find(|v| v.eq_ignore_ascii_case(raw_str.trim()))
+                    .ok_or_else(|| format!("{:?} must be one of {}, got {:?}", name, values.join(", "), raw_str))?;
+                ArgValue::Enum(matched.clone())
+            }
+        };
+        parsed.insert(name.to_string(), value);
+    }
+    if let Some(button) = button {
+        if let Some(missing) = button.arguments.iter().find(|a| !a.optional && !parsed.contains_key(&a.name)) {
+            return Err(format!("{:?} is required", missing.name));
+        }
+    }
+    Ok(parsed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn schedule_button() -> Button {
+        Button::new("schedule", "Schedule", "Schedule!")
+            .new_window(true)
+            .argument(ButtonArgument::number("minutes"))
+            .argument(ButtonArgument::enumeration("unit", vec!["Minutes", "Hours"]))
+            .argument(ButtonArgument::text("note").optional(true))
+    }
+
+    #[test]
+    fn bundled_buttons_parse_and_round_trip() {
+        let buttons = parse_buttons(include_str!("buttons.json")).unwrap();
+        assert!(!buttons.is_empty());
+        let json = serde_json::to_string(&buttons).unwrap();
+        assert_eq!(parse_buttons(&json).unwrap(), buttons);
+    }
+
+    #[test]
+    fn duplicate_button_id_is_rejected() {
+        let buttons = vec![Button::new("a", "A", "A"), Button::new("a", "Other", "Other")];
+        assert!(matches!(validate_buttons(&buttons), Err(ButtonError::DuplicateButtonId(id)) if id == "a"));
+    }
+
+    #[test]
+    fn enum_argument_without_values_is_rejected() {
+        let json = r#"[{"button_id": "a", "button_name": "A", "button_text": "A",
+            "arguments": [{"name": "choice", "type": "enum"}]}]"#;
+        assert!(matches!(parse_buttons(json), Err(ButtonError::MissingEnumValues { argument, .. }) if argument == "choice"));
+    }
+
+    #[test]
+    fn arguments_are_typed_by_the_button() {
+        let arguments = json!([
+            {"name": "minutes", "value": "15"},
+            {"name": "unit", "value": "hours"},
+        ]);
+        let parsed = parse_arguments(Some(&schedule_button()), &arguments).unwrap();
+        assert_eq!(parsed["minutes"], ArgValue::Number(15.0));
+        assert_eq!(parsed["unit"], ArgValue::Enum("Hours".to_string()));
+        assert!(!parsed.contains_key("note"));
+    }
+
+    #[test]
+    fn non_numeric_number_is_rejected() {
+        let arguments = json!([{"name": "minutes", "value": "soon"}, {"name": "unit", "value": "Hours"}]);
+        let error = parse_arguments(Some(&schedule_button()), &arguments).unwrap_err();
+        assert!(error.contains("must be a number"), "{}", error);
+    }
+
+    #[test]
+    fn unknown_enum_value_is_rejected() {
+        let arguments = json!([{"name": "minutes", "value": 5}, {"name": "unit", "value": "Days"}]);
+        let error = parse_arguments(Some(&schedule_button()), &arguments).unwrap_err();
+        assert!(error.contains("must be one of Minutes, Hours"), "{}", error);
+    }
+
+    #[test]
+    fn missing_required_argument_is_rejected() {
+        let arguments = json!([{"name": "minutes", "value": 5}, {"name": "unit", "value": ""}]);
+        let error = parse_arguments(Some(&schedule_button()), &arguments).unwrap_err();
+        assert_eq!(error, "\"unit\" is required");
+    }
+}
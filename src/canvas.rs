@@ -0,0 +1,85 @@
+use serde_derive::{Serialize, Deserialize};
+use std::collections::{HashMap, HashSet};
+
+/// One element of the canvas shown next to a channel: a block of text or an image.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CanvasItem {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub width: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub height: Option<u32>,
+}
+
+impl CanvasItem {
+    pub fn text(text: impl Into<String>) -> Self {
+        CanvasItem { text: Some(text.into()), path: None, width: None, height: None }
+    }
+
+    /// An image; `path` is a URL such as the one returned by `upload_file`.
+    pub fn image(path: impl Into<String>) -> Self {
+        CanvasItem { text: None, path: Some(path.into()), width: None, height: None }
+    }
+
+    pub fn with_size(mut self, width: u32, height: u32) -> Self {
+        self.width = Some(width);
+        self.height = Some(height);
+        self
+    }
+}
+
+/// The canvas of each channel, with optional per-user overrides and users who hid it.
+#[derive(Debug, Default)]
+pub struct CanvasState {
+    channels: HashMap<String, Vec<CanvasItem>>,
+    users: HashMap<(String, String), Vec<CanvasItem>>,
+    hidden: HashSet<(String, String)>,
+}
+
+impl CanvasState {
+    pub fn new() -> Self {
+        CanvasState::default()
+    }
+
+    pub fn set_channel(&mut self, channel_id: &str, items: Vec<CanvasItem>) {
+        self.channels.insert(channel_id.to_string(), items);
+    }
+
+    pub fn set_user(&mut self, channel_id: &str, user_id: &str, items: Vec<CanvasItem>) {
+        self.users.insert((channel_id.to_string(), user_id.to_string()), items);
+    }
+
+    /// Drops a user's override so they see the channel canvas again.
+    pub fn clear_user(&mut self, channel_id: &str, user_id: &str) {
+        self.users.remove(&(channel_id.to_string(), user_id.to_string()));
+    }
+
+    pub fn hide(&mut self, channel_id: &str, user_id: &str) {
+        self.hidden.insert((channel_id.to_string(), user_id.to_string()));
+    }
+
+    pub fn show(&mut self, channel_id: &str, user_id: &str) {
+        self.hidden.remove(&(channel_id.to_string(), user_id.to_string()));
+    }
+
+    /// Whether `user_id` sees the channel canvas, i.e. has no override and has not hidden it.
+    pub fn follows_channel(&self, channel_id: &str, user_id: &str) -> bool {
+        let key = (channel_id.to_string(), user_id.to_string());
+        !self.users.contains_key(&key) && !self.hidden.contains(&key)
+    }
+
+    /// The canvas `user_id` currently sees in `channel_id`.
+    pub fn canvas_for(&self, channel_id: &str, user_id: &str) -> Vec<CanvasItem> {
+        let key = (channel_id.to_string(), user_id.to_string());
+        if self.hidden.contains(&key) {
+            return Vec::new();
+        }
+        self.users.get(&key)
+            .or_else(|| self.channels.get(channel_id))
+            .cloned()
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,44 @@
+use serde_derive::{Serialize, Deserialize};
+use serde_json::{Map, Value};
+
+/// Name and description of a channel as shown to its members.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ChannelInfo {
+    pub channel_id: String,
+    pub channel_name: String,
+    #[serde(default)]
+    pub channel_description: String,
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub context: Map<String, Value>,
+}
+
+impl ChannelInfo {
+    pub fn new(channel_id: impl Into<String>, channel_name: impl Into<String>, channel_description: impl Into<String>) -> Self {
+        ChannelInfo {
+            channel_id: channel_id.into(),
+            channel_name: channel_name.into(),
+            channel_description: channel_description.into(),
+            context: Map::new(),
+        }
+    }
+}
+
+/// How one UI widget (e.g. `"canvas"`) is laid out in the playground.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StyleItem {
+    pub widget: String,
+    /// `"visible"`, `"hidden"` or `"highlight"`.
+    pub display: String,
+    #[serde(default)]
+    pub expand: bool,
+}
+
+impl StyleItem {
+    pub fn new(widget: impl Into<String>, display: impl Into<String>, expand: bool) -> Self {
+        StyleItem {
+            widget: widget.into(),
+            display: display.into(),
+            expand,
+        }
+    }
+}
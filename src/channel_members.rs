@@ -0,0 +1,44 @@
+use std::collections::HashMap;
+
+/// Cached list of real characters per channel, kept current from join/leave events
+/// so that messages to a whole channel need no `fetch_real_characters` round trip.
+#[derive(Debug, Default)]
+pub struct ChannelMembers {
+    members: HashMap<String, Vec<String>>,
+}
+
+impl ChannelMembers {
+    pub fn new() -> Self {
+        ChannelMembers {
+            members: HashMap::new(),
+        }
+    }
+
+    pub fn get(&self, channel_id: &str) -> Option<&Vec<String>> {
+        self.members.get(channel_id)
+    }
+
+    pub fn set(&mut self, channel_id: &str, character_ids: Vec<String>) {
+        self.members.insert(channel_id.to_string(), character_ids);
+    }
+
+    /// Records that `character_id` joined. Ignored for channels that were never fetched,
+    /// since a partial list would be mistaken for the full membership.
+    pub fn join(&mut self, channel_id: &str, character_id: &str) {
+        if let Some(ids) = self.members.get_mut(channel_id) {
+            if !ids.iter().any(|id| id == character_id) {
+                ids.push(character_id.to_string());
+            }
+        }
+    }
+
+    pub fn leave(&mut self, channel_id: &str, character_id: &str) {
+        if let Some(ids) = self.members.get_mut(channel_id) {
+            ids.retain(|id| id != character_id);
+        }
+    }
+
+    pub fn invalidate(&mut self, channel_id: &str) {
+        self.members.remove(channel_id);
+    }
+}
@@ -0,0 +1,256 @@
+use crate::sdk::{Moobius};
+use crate::routing::{Handler, HandlerFuture};
+
+use serde_json::Value;
+use std::sync::Arc;
+
+/// Decides whether `sender` may run a command in `channel_id`.
+pub type Permission = Arc<dyn Fn(&str, &str) -> bool + Send + Sync>;
+
+/// A parsed text command sent to the service through `message_up`.
+#[derive(Debug, Clone)]
+pub struct CommandInvocation {
+    pub channel_id: String,
+    pub sender: String,
+    /// The command's registered name, even if it was invoked through an alias.
+    pub name: String,
+    pub args: Vec<String>,
+    pub body: Value,
+}
+
+pub struct Command {
+    pub name: String,
+    pub aliases: Vec<String>,
+    pub description: String,
+    pub usage: Option<String>,
+    /// Messages with more arguments than this are not treated as this command.
+    pub max_args: Option<usize>,
+    permission: Option<Permission>,
+    handler: Handler<CommandInvocation>,
+}
+
+impl Command {
+    pub fn new<F>(name: &str, description: &str, handler: F) -> Self
+    where
+        F: for<'a> Fn(&'a mut Moobius, CommandInvocation) -> HandlerFuture<'a> + Send + Sync + 'static,
+    {
+        Command {
+            name: name.to_lowercase(),
+            aliases: Vec::new(),
+            description: description.to_string(),
+            usage: None,
+            max_args: None,
+            permission: None,
+            handler: Arc::new(handler),
+        }
+    }
+
+    pub fn alias(mut self, alias: &str) -> Self {
+        self.aliases.push(alias.to_lowercase());
+        self
+    }
+
+    /// Describes the arguments in the help text, e.g. `"<name> [count]"`.
+    pub fn usage(mut self, usage: &str) -> Self {
+        self.usage = Some(usage.to_string());
+        self
+    }
+
+    /// Only matches messages with at most `max_args` arguments, so that e.g. with
+    /// no prefix "reset the timer" is left as chat instead of running `reset`.
+    pub fn max_args(mut self, max_args: usize) -> Self {
+        self.max_args = Some(max_args);
+        self
+    }
+
+    pub fn permission(mut self, permission: impl Fn(&str, &str) -> bool + Send + Sync + 'static) -> Self {
+        self.permission = Some(Arc::new(permission));
+        self
+    }
+
+    fn matches(&self, word: &str) -> bool {
+        self.name == word || self.aliases.iter().any(|a| a == word)
+    }
+}
+
+/// The outcome of matching a message against the registered commands.
+pub enum CommandMatch {
+    Run(Handler<CommandInvocation>, CommandInvocation),
+    Denied(CommandInvocation),
+}
+
+/// Registered text commands, matched against incoming text messages.
+pub struct CommandRegistry {
+    prefixes: Vec<String>,
+    commands: Vec<Command>,
+}
+
+impl CommandRegistry {
+    /// A registry whose commands are typed without a prefix, e.g. `show`.
+    pub fn new() -> Self {
+        CommandRegistry {
+            prefixes: vec![String::new()],
+            commands: Vec::new(),
+        }
+    }
+
+    /// Only accepts commands starting with one of `prefixes`, e.g. `["/", "!"]`.
+    pub fn with_prefixes(prefixes: &[&str]) -> Self {
+        let mut prefixes: Vec<String> = prefixes.iter().map(|p| p.to_string()).collect();
+        // Try longer prefixes first so "!!" is not consumed as "!".
+        prefixes.sort_by_key(|p| std::cmp::Reverse(p.len()));
+        CommandRegistry {
+            prefixes,
+            commands: Vec::new(),
+        }
+    }
+
+    /// Registers `command`, replacing any command with the same name.
+    pub fn register(&mut self, command: Command) {
+        self.commands.retain(|c| c.name != command.name);
+        self.commands.push(command);
+    }
+
+    pub fn commands(&self) -> &[Command] {
+        &self.commands
+    }
+
+    /// Matches `text` against the registered commands. Returns `None` if `text` is not a command.
+    pub fn parse(&self, text: &str, channel_id: &str, sender: &str, body: &Value) -> Option<CommandMatch> {
+        let text = text.trim();
+        let rest = self.prefixes.iter().find_map(|p| text.strip_prefix(p.as_str()))?;
+        let mut words = split_args(rest).into_iter();
+        let word = words.next()?.to_lowercase();
+        let command = self.commands.iter().find(|c| c.matches(&word))?;
+        let args: Vec<String> = words.collect();
+        if command.max_args.is_some_and(|max| args.len() > max) {
+            return None;
+        }
+        let invocation = CommandInvocation {
+            channel_id: channel_id.to_string(),
+            sender: sender.to_string(),
+            name: command.name.clone(),
+            args,
+            body: body.clone(),
+        };
+        match &command.permission {
+            Some(allowed) if !allowed(channel_id, sender) => Some(CommandMatch::Denied(invocation)),
+            _ => Some(CommandMatch::Run(command.handler.clone(), invocation)),
+        }
+    }
+
+    /// Help text listing every command, for sending to users.
+    pub fn help_text(&self) -> String {
+        let prefix = self.prefixes.last().map(String::as_str).unwrap_or_default();
+        self.commands.iter().map(|c| {
+            let mut line = format!("\"{}{}\"", prefix, c.name);
+            if let Some(usage) = &c.usage {
+                line.push(' ');
+                line.push_str(usage);
+            }
+            if !c.aliases.is_empty() {
+                line.push_str(&format!(" (or {})", c.aliases.join(", ")));
+            }
+            format!("{}: {}", line, c.description)
+        }).collect::<Vec<_>>().join("\n\n")
+    }
+}
+
+impl Default for CommandRegistry {
+    fn default() -> Self {
+        CommandRegistry::new()
+    }
+}
+
+/// Splits on whitespace, keeping double-quoted sections together.
+fn split_args(text: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut in_quotes = false;
+    let mut has_arg = false;
+    for c in text.chars() {
+        match c {
+            '"' => {
+                in_quotes = !in_quotes;
+                has_arg = true;
+            }
+            c if c.is_whitespace() && !in_quotes => {
+                if has_arg {
+                    args.push(std::mem::take(&mut current));
+                    has_arg = false;
+                }
+            }
+            c => {
+                current.push(c);
+                has_arg = true;
+            }
+        }
+    }
+    if has_arg {
+        args.push(current);
+    }
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn command(name: &str) -> Command {
+        Command::new(name, "", |_, _| Box::pin(async { Ok(()) }))
+    }
+
+    fn invocation(registry: &CommandRegistry, text: &str) -> Option<CommandInvocation> {
+        match registry.parse(text, "channel", "user", &json!({}))? {
+            CommandMatch::Run(_, invocation) => Some(invocation),
+            CommandMatch::Denied(_) => panic!("{:?} was denied", text),
+        }
+    }
+
+    #[test]
+    fn longer_prefixes_are_tried_first() {
+        let mut registry = CommandRegistry::with_prefixes(&["!", "!!"]);
+        registry.register(command("roll"));
+        registry.register(command("!roll"));
+        assert_eq!(invocation(&registry, "!!roll").unwrap().name, "roll");
+        assert_eq!(invocation(&registry, "!roll").unwrap().name, "roll");
+        assert!(invocation(&registry, "roll").is_none());
+    }
+
+    #[test]
+    fn aliases_resolve_to_the_command_name() {
+        let mut registry = CommandRegistry::with_prefixes(&["/"]);
+        registry.register(command("reset").alias("Clear"));
+        let invocation = invocation(&registry, "/CLEAR now").unwrap();
+        assert_eq!(invocation.name, "reset");
+        assert_eq!(invocation.args, vec!["now"]);
+    }
+
+    #[test]
+    fn quoted_arguments_stay_together() {
+        let mut registry = CommandRegistry::new();
+        registry.register(command("say"));
+        let invocation = invocation(&registry, r#"say "hello  there" world """#).unwrap();
+        assert_eq!(invocation.args, vec!["hello  there", "world", ""]);
+    }
+
+    #[test]
+    fn too_many_arguments_is_not_a_command() {
+        let mut registry = CommandRegistry::new();
+        registry.register(command("reset").max_args(1));
+        assert!(invocation(&registry, "reset all").is_some());
+        assert!(invocation(&registry, "reset the timer").is_none());
+    }
+
+    #[test]
+    fn permission_denied_commands_are_reported() {
+        let mut registry = CommandRegistry::new();
+        registry.register(command("kick").permission(|_, sender| sender == "admin"));
+        assert!(matches!(registry.parse("kick bob", "channel", "admin", &json!({})), Some(CommandMatch::Run(..))));
+        match registry.parse("kick bob", "channel", "user", &json!({})) {
+            Some(CommandMatch::Denied(invocation)) => assert_eq!(invocation.args, vec!["bob"]),
+            _ => panic!("kick was not denied"),
+        }
+    }
+}
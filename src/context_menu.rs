@@ -0,0 +1,56 @@
+use crate::types::{MessageContent};
+
+use serde_derive::{Serialize, Deserialize};
+use serde_json::Value;
+
+/// An entry of the menu users get when right-clicking a message.
+/// The entry is only offered on messages whose subtype is in `support_subtype`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MenuItem {
+    pub item_id: String,
+    pub item_name: String,
+    pub support_subtype: Vec<String>,
+}
+
+impl MenuItem {
+    /// An entry offered on text messages only.
+    pub fn new(item_id: impl Into<String>, item_name: impl Into<String>) -> Self {
+        MenuItem {
+            item_id: item_id.into(),
+            item_name: item_name.into(),
+            support_subtype: vec!["text".to_string()],
+        }
+    }
+
+    pub fn subtypes<S: Into<String>>(mut self, subtypes: impl IntoIterator<Item = S>) -> Self {
+        self.support_subtype = subtypes.into_iter().map(Into::into).collect();
+        self
+    }
+}
+
+/// A `menu_click`: which entry was chosen on which message.
+#[derive(Debug, Clone)]
+pub struct MenuClick {
+    pub channel_id: String,
+    pub item_id: String,
+    pub sender: String,
+    pub message_id: String,
+    pub message_subtype: String,
+    pub message_content: Option<MessageContent>,
+    pub body: Value,
+}
+
+impl MenuClick {
+    pub fn from_body(body: &Value) -> Option<Self> {
+        let message_subtype = body["message_subtype"].as_str().unwrap_or_default().to_string();
+        Some(MenuClick {
+            channel_id: body["channel_id"].as_str()?.to_string(),
+            item_id: body["item_id"].as_str()?.to_string(),
+            sender: body["sender"].as_str()?.to_string(),
+            message_id: body["message_id"].as_str().unwrap_or_default().to_string(),
+            message_content: MessageContent::from_body(&message_subtype, &body["message_content"]),
+            message_subtype,
+            body: body.clone(),
+        })
+    }
+}
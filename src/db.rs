@@ -1,4 +1,6 @@
 use std::collections::HashMap;
+use std::fs;
+use std::io;
 use serde_json::Value;
 
 #[derive(Debug, Default)]
@@ -13,6 +15,21 @@ impl MoobiusDatabase {
         }
     }
 
+    /// Loads a database written by `save`; a missing file gives an empty database.
+    pub fn load(path: &str) -> io::Result<Self> {
+        let data = match fs::read_to_string(path) {
+            Ok(contents) => serde_json::from_str(&contents)?,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(e),
+        };
+        Ok(MoobiusDatabase { data })
+    }
+
+    /// Writes all fields to `path` as JSON.
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        fs::write(path, serde_json::to_vec(&self.data)?)
+    }
+
     pub fn add_field(&mut self, key: &str, value: Value) {
         self.data.insert(key.to_string(), value);
     }
@@ -0,0 +1,290 @@
+use crate::socket::{WebSocket, JsonProtocol};
+use crate::transport::{Frame, ForwardTransport};
+use crate::http_api::{HttpApi};
+use crate::service_group_lib::{ServiceGroupLib};
+use crate::types::{Config, MessageContent, Recipients, SentMessage};
+
+use serde_derive::{Serialize, Deserialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tokio::sync::{mpsc, Notify, Semaphore};
+
+pub type TaskFuture = Pin<Box<dyn Future<Output = Result<(), Box<dyn std::error::Error>>> + Send>>;
+
+/// A handler that runs on its own task, concurrently with the listen loop and other
+/// task handlers. It cannot borrow `Moobius`; it gets a `TaskContext` instead.
+pub type TaskHandler = Arc<dyn Fn(TaskContext) -> TaskFuture + Send + Sync>;
+
+/// Which payloads must be handled one after another, in the order they arrived.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum OrderingKey {
+    #[default]
+    Channel,
+    Sender,
+}
+
+/// Settings for task handlers, e.g. from `Config::concurrency`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ConcurrencyConfig {
+    /// Task handlers that may run at the same time.
+    pub max_handlers: usize,
+    #[serde(default)]
+    pub ordering: OrderingKey,
+}
+
+impl Default for ConcurrencyConfig {
+    fn default() -> Self {
+        ConcurrencyConfig { max_handlers: 16, ordering: OrderingKey::Channel }
+    }
+}
+
+/// What a task handler gets to work with.
+pub struct TaskContext {
+    pub config: Config,
+    pub payload: Value,
+    /// Messages sent here go out through the `Moobius` connection, including its
+    /// middleware, outbound queue and outbox.
+    pub ws_client: WebSocket<JsonProtocol>,
+    /// The `Moobius` HTTP client, e.g. for uploads.
+    pub http_client: Arc<dyn HttpApi>,
+    /// Shares its groups with `Moobius`, so recipients resolve to the same group IDs.
+    pub service_group_lib: ServiceGroupLib,
+}
+
+impl TaskContext {
+    pub fn body(&self) -> &Value {
+        &self.payload["body"]
+    }
+
+    /// Resolves `recipients` to a group ID, like `Moobius::resolve_recipients`. Task
+    /// handlers have no channel member cache, so `Recipients::Channel` asks the HTTP API.
+    /// Takes `&mut self` so the future stays `Send`; `ws_client` is not `Sync`.
+    pub async fn resolve_recipients(&mut self, channel_id: &str, recipients: Recipients) -> Result<String, Box<dyn std::error::Error>> {
+        let character_ids = match recipients {
+            Recipients::Group(group_id) => return Ok(group_id),
+            Recipients::Characters(character_ids) => character_ids,
+            Recipients::Channel => {
+                let service_id = self.config.service_id.as_ref().ok_or("service_id is not configured")?;
+                self.http_client.fetch_real_characters(channel_id, service_id)?
+            }
+        };
+        self.service_group_lib.convert_list(self.http_client.as_ref(), character_ids, true, None).await
+    }
+
+    /// Sends `content` as `sender` to `recipients` in `channel_id`.
+    pub async fn send_message(
+        &mut self,
+        content: &MessageContent,
+        channel_id: &str,
+        sender: &str,
+        recipients: Recipients,
+    ) -> Result<SentMessage, Box<dyn std::error::Error>> {
+        let group_id = self.resolve_recipients(channel_id, recipients).await?;
+        if group_id.is_empty() {
+            return Err("No recipients to send the message to".into());
+        }
+        let service_id = self.config.service_id.clone().ok_or("service_id is not configured")?;
+        let message = self.ws_client.message_down(&service_id, channel_id, &group_id, content, sender).await?;
+        SentMessage::new(channel_id, &group_id, message)
+    }
+
+    /// Uploads the file at `file_path` and sends it as an image message.
+    pub async fn send_image_message(
+        &mut self,
+        file_path: &str,
+        channel_id: &str,
+        sender: &str,
+        recipients: Recipients,
+    ) -> Result<SentMessage, Box<dyn std::error::Error>> {
+        let image_url = self.http_client.upload_file(file_path)?;
+        self.send_message(&MessageContent::image(image_url), channel_id, sender, recipients).await
+    }
+}
+
+type Job = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// Counts task handlers that are queued or running.
+struct InFlight {
+    count: AtomicUsize,
+    idle: Notify,
+}
+
+impl InFlight {
+    fn finish(&self) {
+        if self.count.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.idle.notify();
+        }
+    }
+}
+
+/// Marks a task handler as finished when dropped, so one that panics is counted too.
+struct Finished(Arc<InFlight>);
+
+impl Drop for Finished {
+    fn drop(&mut self) {
+        self.0.finish();
+    }
+}
+
+/// A worker task handling one ordering key, and how many of its jobs are unfinished.
+struct Worker {
+    tx: mpsc::UnboundedSender<Job>,
+    pending: Arc<AtomicUsize>,
+}
+
+/// Runs task handlers on spawned tasks, at most `max_handlers` at a time. Payloads
+/// with the same ordering key go through one worker queue, so they are handled in
+/// the order they arrived. A worker exits once it has nothing left to do.
+pub struct Dispatcher {
+    handlers: HashMap<String, TaskHandler>,
+    ordering: OrderingKey,
+    semaphore: Arc<Semaphore>,
+    workers: HashMap<String, Worker>,
+    forward_tx: mpsc::UnboundedSender<Frame>,
+    forward_rx: mpsc::UnboundedReceiver<Frame>,
+    in_flight: Arc<InFlight>,
+}
+
+impl Dispatcher {
+    pub fn new(config: &ConcurrencyConfig) -> Self {
+        let (forward_tx, forward_rx) = mpsc::unbounded_channel();
+        Dispatcher {
+            handlers: HashMap::new(),
+            ordering: config.ordering,
+            semaphore: Arc::new(Semaphore::new(config.max_handlers.max(1))),
+            workers: HashMap::new(),
+            forward_tx,
+            forward_rx,
+            in_flight: Arc::new(InFlight { count: AtomicUsize::new(0), idle: Notify::new() }),
+        }
+    }
+
+    /// Runs `handler` on a task for payloads of type `payload_type` (e.g. `message_up`)
+    /// instead of the built-in handling.
+    ///
+    /// ```ignore
+    /// moobius.dispatcher.on("message_up", |mut ctx| Box::pin(async move {
+    ///     let channel_id = ctx.body()["channel_id"].as_str().unwrap_or_default().to_string();
+    ///     let sender = ctx.body()["sender"].as_str().unwrap_or_default().to_string();
+    ///     let service_id = ctx.config.service_id.clone().unwrap_or_default();
+    ///     ctx.send_image_message("cat.png", &channel_id, &service_id, Recipients::Characters(vec![sender])).await?;
+    ///     Ok(())
+    /// }));
+    /// ```
+    pub fn on<F>(&mut self, payload_type: &str, handler: F)
+    where
+        F: Fn(TaskContext) -> TaskFuture + Send + Sync + 'static,
+    {
+        self.handlers.insert(payload_type.to_string(), Arc::new(handler));
+    }
+
+    pub fn remove(&mut self, payload_type: &str) {
+        self.handlers.remove(payload_type);
+    }
+
+    pub fn handles(&self, payload_type: &str) -> bool {
+        self.handlers.contains_key(payload_type)
+    }
+
+    fn key(&self, payload: &Value) -> String {
+        let field = match self.ordering {
+            OrderingKey::Channel => "channel_id",
+            OrderingKey::Sender => "sender",
+        };
+        payload["body"][field].as_str().unwrap_or_default().to_string()
+    }
+
+    /// Queues `payload` for its task handler, or hands it back if none is registered.
+    pub fn dispatch(&mut self, config: &Config, http_client: &Arc<dyn HttpApi>, service_group_lib: &ServiceGroupLib, payload: Value) -> Option<Value> {
+        let handler = match payload["type"].as_str().and_then(|t| self.handlers.get(t)) {
+            Some(handler) => handler.clone(),
+            None => return Some(payload),
+        };
+        let payload_type = payload["type"].as_str().unwrap_or_default().to_string();
+        let key = self.key(&payload);
+        let context = TaskContext {
+            config: config.clone(),
+            payload,
+            ws_client: WebSocket::with_transport(JsonProtocol, ForwardTransport::new(self.forward_tx.clone())),
+            http_client: http_client.clone(),
+            service_group_lib: service_group_lib.clone(),
+        };
+        self.in_flight.count.fetch_add(1, Ordering::SeqCst);
+        let finished = Finished(self.in_flight.clone());
+        let job: Job = Box::pin(async move {
+            let _finished = finished;
+            if let Err(e) = handler(context).await {
+                println!("Error in {} task handler: {}", payload_type, e);
+            }
+        });
+
+        // Dropping an idle worker's sender ends its task; only this method sends, so
+        // nothing can be queued on it in between.
+        self.workers.retain(|_, worker| worker.pending.load(Ordering::SeqCst) > 0);
+        let job = match self.workers.get(&key) {
+            Some(worker) => {
+                worker.pending.fetch_add(1, Ordering::SeqCst);
+                match worker.tx.send(job) {
+                    Ok(()) => return None,
+                    // The worker is gone; start a new one.
+                    Err(mpsc::error::SendError(job)) => job,
+                }
+            }
+            None => job,
+        };
+        let worker = self.spawn_worker();
+        worker.pending.fetch_add(1, Ordering::SeqCst);
+        // A new worker's receiver is alive, so this cannot fail.
+        let _ = worker.tx.send(job);
+        self.workers.insert(key, worker);
+        None
+    }
+
+    fn spawn_worker(&self) -> Worker {
+        let (tx, mut rx) = mpsc::unbounded_channel::<Job>();
+        let pending = Arc::new(AtomicUsize::new(0));
+        let semaphore = self.semaphore.clone();
+        let worker_pending = pending.clone();
+        tokio::spawn(async move {
+            while let Some(job) = rx.recv().await {
+                let _permit = semaphore.acquire().await;
+                // On its own task, so a panicking handler doesn't take the worker down with it.
+                if tokio::spawn(job).await.is_err() {
+                    println!("Task handler panicked");
+                }
+                worker_pending.fetch_sub(1, Ordering::SeqCst);
+            }
+        });
+        Worker { tx, pending }
+    }
+
+    /// Task handlers that are queued or running.
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.count.load(Ordering::SeqCst)
+    }
+
+    /// Completes once no task handlers are queued or running.
+    pub fn idle(&self) -> impl Future<Output = ()> + Send + 'static {
+        let in_flight = self.in_flight.clone();
+        async move {
+            while in_flight.count.load(Ordering::SeqCst) > 0 {
+                in_flight.idle.notified().await;
+            }
+        }
+    }
+
+    /// A frame a task handler sent, if one is waiting.
+    pub(crate) fn try_next_outgoing(&mut self) -> Option<Frame> {
+        self.forward_rx.try_recv().ok()
+    }
+
+    /// Waits for the next frame a task handler sent.
+    pub(crate) async fn next_outgoing(&mut self) -> Option<Frame> {
+        self.forward_rx.recv().await
+    }
+}
@@ -0,0 +1,148 @@
+use crate::types::{Character};
+
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+
+pub type HttpFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T, Box<dyn std::error::Error>>> + Send + 'a>>;
+
+/// The Moobius HTTP API operations used by the SDK. Implemented by `HTTPAPIWrapper`
+/// for the real server and by `FakeHttpApi` for tests.
+pub trait HttpApi: Send + Sync {
+    /// Returns the access token and refresh token.
+    fn authenticate(&self) -> Result<(String, String), Box<dyn std::error::Error>>;
+    fn fetch_user_info(&self) -> Result<Value, Box<dyn std::error::Error>>;
+    fn create_character(&self, service_id: &str, name: &str, avatar: &str, description: &str) -> Result<Character, Box<dyn std::error::Error>>;
+    /// Uploads a local file and returns its URL.
+    fn upload_file(&self, file_path: &str) -> Result<String, Box<dyn std::error::Error>>;
+    fn fetch_real_characters(&self, channel_id: &str, service_id: &str) -> Result<Vec<String>, Box<dyn std::error::Error>>;
+    fn create_service_group(&self, character_ids: Vec<String>) -> HttpFuture<'_, String>;
+    fn create_channel_group<'a>(&'a self, channel_id: &'a str, group_name: &'a str, character_ids: Vec<String>) -> HttpFuture<'a, String>;
+}
+
+#[derive(Debug, Default)]
+struct FakeState {
+    channel_characters: HashMap<String, Vec<String>>,
+    groups: HashMap<String, Vec<String>>,
+    characters: Vec<Character>,
+    uploads: Vec<String>,
+    next_id: u64,
+    failing: bool,
+}
+
+impl FakeState {
+    fn next_id(&mut self, prefix: &str) -> String {
+        self.next_id += 1;
+        format!("{}-{}", prefix, self.next_id)
+    }
+
+    fn check(&self) -> Result<(), Box<dyn std::error::Error>> {
+        if self.failing {
+            Err("FakeHttpApi is set to fail".into())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// An in-memory `HttpApi` that records what it was asked to do.
+/// Clones share their state, so a test can keep a clone to inspect after handing one to `Moobius`.
+#[derive(Debug, Default, Clone)]
+pub struct FakeHttpApi {
+    state: Arc<Mutex<FakeState>>,
+}
+
+impl FakeHttpApi {
+    pub fn new() -> Self {
+        FakeHttpApi::default()
+    }
+
+    /// Sets what `fetch_real_characters` returns for `channel_id`.
+    pub fn with_channel_characters(self, channel_id: &str, character_ids: Vec<String>) -> Self {
+        self.state.lock().unwrap().channel_characters.insert(channel_id.to_string(), character_ids);
+        self
+    }
+
+    /// Makes every call fail until set back to `false`.
+    pub fn set_failing(&self, failing: bool) {
+        self.state.lock().unwrap().failing = failing;
+    }
+
+    /// Groups created so far, by group ID.
+    pub fn groups(&self) -> HashMap<String, Vec<String>> {
+        self.state.lock().unwrap().groups.clone()
+    }
+
+    pub fn characters(&self) -> Vec<Character> {
+        self.state.lock().unwrap().characters.clone()
+    }
+
+    /// Paths of the files uploaded so far.
+    pub fn uploads(&self) -> Vec<String> {
+        self.state.lock().unwrap().uploads.clone()
+    }
+
+    fn create_group(&self, prefix: &str, character_ids: Vec<String>) -> Result<String, Box<dyn std::error::Error>> {
+        let mut state = self.state.lock().unwrap();
+        state.check()?;
+        let group_id = state.next_id(prefix);
+        state.groups.insert(group_id.clone(), character_ids);
+        Ok(group_id)
+    }
+}
+
+impl HttpApi for FakeHttpApi {
+    fn authenticate(&self) -> Result<(String, String), Box<dyn std::error::Error>> {
+        self.state.lock().unwrap().check()?;
+        Ok(("fake-access-token".to_string(), "fake-refresh-token".to_string()))
+    }
+
+    fn fetch_user_info(&self) -> Result<Value, Box<dyn std::error::Error>> {
+        self.state.lock().unwrap().check()?;
+        Ok(json!({"user_id": "fake-user"}))
+    }
+
+    fn create_character(&self, _service_id: &str, name: &str, avatar: &str, description: &str) -> Result<Character, Box<dyn std::error::Error>> {
+        let mut state = self.state.lock().unwrap();
+        state.check()?;
+        let mut character_context = Map::new();
+        character_context.insert("name".to_string(), json!(name));
+        character_context.insert("avatar".to_string(), json!(avatar));
+        character_context.insert("description".to_string(), json!(description));
+        let character = Character {
+            character_id: state.next_id("character"),
+            name: name.to_string(),
+            avatar: avatar.to_string(),
+            description: description.to_string(),
+            character_context,
+        };
+        state.characters.push(character.clone());
+        Ok(character)
+    }
+
+    fn upload_file(&self, file_path: &str) -> Result<String, Box<dyn std::error::Error>> {
+        let mut state = self.state.lock().unwrap();
+        state.check()?;
+        state.uploads.push(file_path.to_string());
+        Ok(format!("https://fake.moobius.test/{}", file_path))
+    }
+
+    fn fetch_real_characters(&self, channel_id: &str, _service_id: &str) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let state = self.state.lock().unwrap();
+        state.check()?;
+        Ok(state.channel_characters.get(channel_id).cloned().unwrap_or_default())
+    }
+
+    fn create_service_group(&self, character_ids: Vec<String>) -> HttpFuture<'_, String> {
+        // Keep the error as a String so the future stays Send.
+        let created = self.create_group("group", character_ids).map_err(|e| e.to_string());
+        Box::pin(async move { created.map_err(Into::into) })
+    }
+
+    fn create_channel_group<'a>(&'a self, _channel_id: &'a str, _group_name: &'a str, character_ids: Vec<String>) -> HttpFuture<'a, String> {
+        let created = self.create_group("channel-group", character_ids).map_err(|e| e.to_string());
+        Box::pin(async move { created.map_err(Into::into) })
+    }
+}
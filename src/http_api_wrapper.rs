@@ -1,15 +1,12 @@
 use crate::types::{Config, Character};
+use crate::http_api::{HttpApi, HttpFuture};
 use reqwest::blocking::{Client};
 use reqwest::header::{HeaderMap, HeaderValue};
-use reqwest::blocking::multipart::{Form, Part};
 use serde_json::json;
 use serde_json::Value;
-use serde_derive::{Deserialize, Serialize};
-use std::sync::Arc;
-use tokio::sync::Mutex;
-use log::{info, error};
 use std::fs::File;
-use std::io::{self, ErrorKind, Read};
+use std::io::Read;
+use std::sync::RwLock;
 
 
 pub struct HTTPAPIWrapper {
@@ -17,9 +14,10 @@ pub struct HTTPAPIWrapper {
     http_server_uri: String,
     email: String,
     password: String,
-    access_token: String,
-    refresh_token: String,
-    headers: HeaderMap,
+    // Behind locks so that a client shared with task handlers can still re-authenticate.
+    access_token: RwLock<String>,
+    refresh_token: RwLock<String>,
+    headers: RwLock<HeaderMap>,
 }
 
 impl HTTPAPIWrapper {
@@ -28,9 +26,9 @@ impl HTTPAPIWrapper {
         let http_server_uri = config.http_server_uri;
         let email = config.email;
         let password = config.password;
-        let access_token = String::new();
-        let refresh_token = String::new();
-        let headers = HeaderMap::new();
+        let access_token = RwLock::new(String::new());
+        let refresh_token = RwLock::new(String::new());
+        let headers = RwLock::new(HeaderMap::new());
         Self {
             http_client,
             http_server_uri,
@@ -45,7 +43,7 @@ impl HTTPAPIWrapper {
     /// Authenticates the user with the Moobius HTTP API.
     /// This method must be called before any other API calls.
     /// It returns a tuple containing the access token and refresh token.
-    pub fn authenticate(&mut self) -> Result<(String, String), Box<dyn std::error::Error>> {
+    pub fn authenticate(&self) -> Result<(String, String), Box<dyn std::error::Error>> {
         let url = format!("{}/auth/sign_in", self.http_server_uri);
         let request_body = json!({
             "username": self.email,
@@ -58,19 +56,40 @@ impl HTTPAPIWrapper {
             .send()?  // Sends the POST request
             .json::<Value>()?;  // Parses the response body as JSON
         
-        self.access_token = response_body["data"]["AuthenticationResult"]["AccessToken"]
+        let access_token = response_body["data"]["AuthenticationResult"]["AccessToken"]
             .as_str()
             .ok_or("Access Token not found in the response")?
             .to_string();
 
-        self.refresh_token = response_body["data"]["AuthenticationResult"]["RefreshToken"]
+        let refresh_token = response_body["data"]["AuthenticationResult"]["RefreshToken"]
             .as_str()
             .ok_or("Refresh Token not found in the response")?
             .to_string();
 
-        self.headers.insert("Auth-Origin", HeaderValue::from_static("cognito"));
-        self.headers.insert("Authorization", HeaderValue::from_str(&("Bearer ".to_string() + &self.access_token))?);
-        Ok((self.access_token.clone(), self.refresh_token.clone()))
+        let mut headers = self.headers.write().unwrap();
+        headers.insert("Auth-Origin", HeaderValue::from_static("cognito"));
+        headers.insert("Authorization", HeaderValue::from_str(&("Bearer ".to_string() + &access_token))?);
+        *self.access_token.write().unwrap() = access_token.clone();
+        *self.refresh_token.write().unwrap() = refresh_token.clone();
+        Ok((access_token, refresh_token))
+    }
+
+    fn headers(&self) -> HeaderMap {
+        self.headers.read().unwrap().clone()
+    }
+
+    /// Fetches the profile of the authenticated user. The returned value contains `user_id`.
+    pub fn fetch_user_info(&self) -> Result<Value, Box<dyn std::error::Error>> {
+        let url = format!("{}/user/info", self.http_server_uri);
+        let response = self.http_client.get(&url)
+            .headers(self.headers())
+            .send()?
+            .json::<Value>()?;
+
+        if response["data"].is_null() {
+            return Err("User info not found in the response".into());
+        }
+        Ok(response["data"].clone())
     }
 
     pub fn create_character(&self, service_id: &str, name: &str, avatar: &str, description: &str) -> Result<Character, Box<dyn std::error::Error>> {
@@ -89,7 +108,7 @@ impl HTTPAPIWrapper {
         // Send POST request
         let response_body = self.http_client.post(&url)
             .json(&request_body)
-            .headers(self.headers.clone())
+            .headers(self.headers())
             .send()?
             .json::<Value>()?;  // Parses the response body as JSON
 
@@ -128,7 +147,7 @@ impl HTTPAPIWrapper {
         let url: String = format!("{}/file/upload", self.http_server_uri);
         let params = [("extension", extension)];
         let response = self.http_client.get(&url)
-            .headers(self.headers.clone())
+            .headers(self.headers())
             .query(&params)
             .send()?
             .json::<Value>()?;
@@ -169,10 +188,10 @@ impl HTTPAPIWrapper {
         if res.status().is_success() {
             let full_url = format!("{}{}", upload_url, upload_fields["key"].as_str().unwrap_or_default());
             println!("Successfully uploaded {} to {}", file_path, full_url);
-            return Ok(full_url);
+            Ok(full_url)
         } else {
             println!("Failed to upload {}", file_path);
-            return Err("Failed to upload".into());
+            Err("Failed to upload".into())
         }
     }
 
@@ -180,7 +199,7 @@ impl HTTPAPIWrapper {
         let url = format!("{}/channel/character_list", self.http_server_uri);
         let params = [("channel_id", channel_id), ("service_id", service_id)];
         let response = self.http_client.get(&url)
-            .headers(self.headers.clone())
+            .headers(self.headers())
             .query(&params)
             .send()?
             .json::<Value>()?;
@@ -205,7 +224,7 @@ impl HTTPAPIWrapper {
             .http_client
             .post(&url)
             .json(&json_request)
-            .headers(self.headers.clone())
+            .headers(self.headers())
             .send()?
             .json::<Value>()?;
         
@@ -216,7 +235,7 @@ impl HTTPAPIWrapper {
             Ok(group_id)
         } else {
             println!("Error creating service group: {:?}", response);
-            Err(Box::new(std::io::Error::new(std::io::ErrorKind::Other, "Failed to create service group")))
+            Err(Box::new(std::io::Error::other("Failed to create service group")))
         }
     }
 
@@ -232,7 +251,7 @@ impl HTTPAPIWrapper {
             .http_client
             .post(&url)
             .json(&json_request)
-            .headers(self.headers.clone())
+            .headers(self.headers())
             .send()?
             .json::<Value>()?;
         
@@ -243,8 +262,38 @@ impl HTTPAPIWrapper {
             Ok(group_id)
         } else {
             println!("Error creating channel group: {:?}", response);
-            Err(Box::new(std::io::Error::new(std::io::ErrorKind::Other, "Failed to create channel group")))
+            Err(Box::new(std::io::Error::other("Failed to create channel group")))
         }
     }
 }
 
+
+impl HttpApi for HTTPAPIWrapper {
+    fn authenticate(&self) -> Result<(String, String), Box<dyn std::error::Error>> {
+        HTTPAPIWrapper::authenticate(self)
+    }
+
+    fn fetch_user_info(&self) -> Result<Value, Box<dyn std::error::Error>> {
+        HTTPAPIWrapper::fetch_user_info(self)
+    }
+
+    fn create_character(&self, service_id: &str, name: &str, avatar: &str, description: &str) -> Result<Character, Box<dyn std::error::Error>> {
+        HTTPAPIWrapper::create_character(self, service_id, name, avatar, description)
+    }
+
+    fn upload_file(&self, file_path: &str) -> Result<String, Box<dyn std::error::Error>> {
+        HTTPAPIWrapper::upload_file(self, file_path)
+    }
+
+    fn fetch_real_characters(&self, channel_id: &str, service_id: &str) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        HTTPAPIWrapper::fetch_real_characters(self, channel_id, service_id)
+    }
+
+    fn create_service_group(&self, character_ids: Vec<String>) -> HttpFuture<'_, String> {
+        Box::pin(HTTPAPIWrapper::create_service_group(self, character_ids))
+    }
+
+    fn create_channel_group<'a>(&'a self, channel_id: &'a str, group_name: &'a str, character_ids: Vec<String>) -> HttpFuture<'a, String> {
+        Box::pin(HTTPAPIWrapper::create_channel_group(self, channel_id, group_name, character_ids))
+    }
+}
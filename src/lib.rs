@@ -1,12 +1,59 @@
 mod socket;
+mod transport;
 mod sdk;
 mod types;
 mod http_api_wrapper;
+mod http_api;
 mod service_group_lib;
 mod db;
+mod text_utils;
+mod channel_members;
+mod agent;
+mod buttons;
+mod routing;
+mod button_state;
+mod canvas;
+mod context_menu;
+mod channel_info;
+mod commands;
+mod relay;
+mod rate_limit;
+mod middleware;
+mod outbound;
+mod outbox;
+mod dispatch;
+mod shutdown;
+mod recorder;
+#[cfg(feature = "testing")]
+pub mod testing;
 
 pub use sdk::{Moobius};
-pub use types::{Config, Character, MessageContent};
+pub use socket::{WebSocket, Protocol, JsonProtocol, ProtocolKind};
+#[cfg(feature = "msgpack")]
+pub use socket::{MessagePackProtocol};
+#[cfg(feature = "cbor")]
+pub use socket::{CborProtocol};
+pub use outbound::{OutboundQueue, OutboundConfig, Priority, OverflowPolicy, Overflow};
+pub use outbox::{Outbox, OutboxConfig};
+pub use dispatch::{Dispatcher, TaskContext, TaskHandler, TaskFuture, ConcurrencyConfig, OrderingKey};
+pub use shutdown::{ShutdownHandle};
+pub use transport::{Transport, TransportFuture, Frame, TungsteniteTransport, InMemoryTransport};
+pub use types::{Config, Character, MessageContent, CardLink, Recipients, SentMessage};
 pub use http_api_wrapper::{HTTPAPIWrapper};
+pub use http_api::{HttpApi, HttpFuture, FakeHttpApi};
 pub use service_group_lib::{ServiceGroupLib};
-pub use db::{MoobiusDatabase};
\ No newline at end of file
+pub use db::{MoobiusDatabase};
+pub use text_utils::{LengthLimit, truncate_chars, split_text};
+pub use channel_members::{ChannelMembers};
+pub use agent::{Agent, AgentEvent};
+pub use buttons::{Button, ButtonArgument, ArgumentType, ButtonError, validate_buttons, parse_buttons, load_buttons, ButtonSource, ButtonProvider, ArgValue, parse_arguments};
+pub use routing::{Router, ButtonRouter, MenuRouter, ButtonClick, Handler, HandlerFuture, HandlerResult};
+pub use button_state::{ButtonState, ButtonChange};
+pub use canvas::{CanvasItem, CanvasState};
+pub use context_menu::{MenuItem, MenuClick};
+pub use channel_info::{ChannelInfo, StyleItem};
+pub use commands::{Command, CommandRegistry, CommandInvocation, CommandMatch, Permission};
+pub use relay::{RelayPolicy, RelayTarget, RelaySender, RelayTransform};
+pub use rate_limit::{TokenBucket};
+pub use middleware::{Middleware, MiddlewareChain, LoggingMiddleware, FilterMiddleware, RewriteMiddleware, AllowListMiddleware, RateLimitMiddleware};
+pub use recorder::{RecordingMiddleware, RecordedMessage, Direction, read_recording};
\ No newline at end of file
@@ -1,5 +1,77 @@
-use moobius::{Moobius, Config, HTTPAPIWrapper};
-use reqwest::Error;
+use moobius::{Moobius, Config, LengthLimit, Recipients, Command, CommandRegistry, ButtonChange, ProtocolKind, ConcurrencyConfig};
+use serde_json::Value;
+
+fn register_buttons(moobius_client: &mut Moobius) {
+    moobius_client.button_router.on("message_btn", |moobius, click| Box::pin(async move {
+        match click.text("Select Task") {
+            Some("Text") => {
+                moobius.send_text_message("Hello, World!".to_string(), &click.channel_id, &click.sender, Recipients::Channel, LengthLimit::Truncate(1000)).await?;
+            },
+            Some("Image") => {
+                let cat_in_plastic_bag = "src/cat_plastic_bag.png";
+                moobius.send_image_message(cat_in_plastic_bag, &click.channel_id, &click.sender, Recipients::Channel).await?;
+            },
+            value => println!("Unknown value message_btn: {:?}", value),
+        }
+        Ok(())
+    }));
+
+    moobius_client.button_router.on("user_btn", |moobius, click| Box::pin(async move {
+        match click.text("Select task") {
+            Some("Make Mickey") => {
+                moobius.create_character("src/mickey.png", "Mickey", "A friendly mouse").await;
+                moobius.refresh_characters().await;
+            },
+            Some("Mickey Talk") => {
+                let last_mickey_id = moobius.db.get_field("virtual_characters")
+                    .and_then(|v| v.as_array())
+                    .and_then(|v| v.last())
+                    .and_then(|v| v["character_id"].as_str())
+                    .ok_or("No Mickey has been made yet")?
+                    .to_string();
+                moobius.send_text_message("M-I-C-K-E-Y M-O-U-S-E!".to_string(), &click.channel_id, &last_mickey_id, Recipients::Characters(vec![click.sender.clone()]), LengthLimit::Truncate(1000)).await?;
+            },
+            value => println!("Unknown value user_btn: {:?}", value),
+        }
+        Ok(())
+    }));
+
+    moobius_client.button_router.on("command_btn", |moobius, click| Box::pin(async move {
+        let cmds = moobius.commands.help_text();
+        moobius.send_text_message(cmds, &click.channel_id, &click.sender, Recipients::Characters(vec![click.sender.clone()]), LengthLimit::Truncate(1000)).await?;
+        Ok(())
+    }));
+}
+
+/// Character IDs allowed to run destructive commands, from a comma-separated `MOOBIUS_ADMINS`.
+fn admins() -> Vec<String> {
+    std::env::var("MOOBIUS_ADMINS").unwrap_or_default()
+        .split(',').map(str::trim).filter(|id| !id.is_empty()).map(String::from).collect()
+}
+
+fn register_commands(moobius_client: &mut Moobius) {
+    moobius_client.commands = CommandRegistry::with_prefixes(&["/"]);
+
+    moobius_client.commands.register(Command::new("show", "Show buttons and canvas.", |moobius, cmd| Box::pin(async move {
+        moobius.canvas_state.show(&cmd.channel_id, &cmd.sender);
+        moobius.send_canvas(&cmd.channel_id, &cmd.sender).await?;
+        moobius.update_user_buttons(&cmd.channel_id, Recipients::Characters(vec![cmd.sender.clone()]), &[ButtonChange::ShowAll]).await
+    })).max_args(0));
+
+    moobius_client.commands.register(Command::new("hide", "Hide buttons and canvas.", |moobius, cmd| Box::pin(async move {
+        moobius.canvas_state.hide(&cmd.channel_id, &cmd.sender);
+        moobius.send_canvas(&cmd.channel_id, &cmd.sender).await?;
+        moobius.update_user_buttons(&cmd.channel_id, Recipients::Characters(vec![cmd.sender.clone()]), &[ButtonChange::HideAll]).await
+    })).max_args(0));
+
+    let admins = admins();
+    moobius_client.commands.register(Command::new("reset", "Reset Mickeys and refresh buttons.", |moobius, cmd| Box::pin(async move {
+        moobius.db.add_field("virtual_characters", Value::Array(vec![]));
+        moobius.refresh_characters().await;
+        moobius.button_state.reset(&cmd.channel_id, &cmd.sender);
+        moobius.send_buttons(&cmd.channel_id, &cmd.sender).await
+    })).max_args(0).permission(move |_channel_id, sender| admins.iter().any(|admin| admin == sender)));
+}
 
 #[tokio::main]
 async fn main() {
@@ -10,10 +82,40 @@ async fn main() {
         password: "".to_string(),
         service_id: Some("".to_string()),
         channels: vec!["".to_string()],
+        buttons_path: Some("src/buttons.json".to_string()),
+        buttons_hot_reload: true,
+        protocol: ProtocolKind::Json,
+        outbound: None,
+        outbox: None,
+        concurrency: ConcurrencyConfig::default(),
+        db_path: None,
+        shutdown_timeout_secs: None,
     };
     
     let mut moobius_client = Moobius::new(config.clone()).await.unwrap();
-    let (access_token, refresh_token) = moobius_client.http_client.authenticate().unwrap();
+    register_buttons(&mut moobius_client);
+    register_commands(&mut moobius_client);
+    let (access_token, _refresh_token) = moobius_client.http_client.authenticate().unwrap();
     let _ = moobius_client.ws_client.service_login(config.service_id.as_ref().unwrap(), &access_token).await.unwrap();    
+    let shutdown = moobius_client.shutdown_handle();
+    tokio::spawn(async move {
+        wait_for_signal().await;
+        shutdown.shutdown();
+    });
     moobius_client.listen().await.unwrap();
-}
\ No newline at end of file
+}
+
+#[cfg(unix)]
+async fn wait_for_signal() {
+    use tokio::signal::unix::{signal, SignalKind};
+    let mut sigterm = signal(SignalKind::terminate()).unwrap();
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {},
+        _ = sigterm.recv() => {},
+    }
+}
+
+#[cfg(not(unix))]
+async fn wait_for_signal() {
+    let _ = tokio::signal::ctrl_c().await;
+}
@@ -0,0 +1,152 @@
+use crate::rate_limit::{TokenBucket};
+
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+
+/// Intercepts traffic on a `WebSocket`. `inbound` sees each received payload before it
+/// is handled and `outbound` each message before it is sent; returning `None` drops it.
+pub trait Middleware: Send {
+    fn inbound(&mut self, payload: Value) -> Option<Value> {
+        Some(payload)
+    }
+
+    fn outbound(&mut self, message: Value) -> Option<Value> {
+        Some(message)
+    }
+}
+
+/// An ordered list of middleware. Inbound payloads pass through it first to last and
+/// outbound messages last to first, so the first layer is the outermost.
+#[derive(Default)]
+pub struct MiddlewareChain {
+    layers: Vec<Box<dyn Middleware>>,
+}
+
+impl MiddlewareChain {
+    pub fn new() -> Self {
+        MiddlewareChain { layers: Vec::new() }
+    }
+
+    pub fn push(&mut self, middleware: impl Middleware + 'static) {
+        self.layers.push(Box::new(middleware));
+    }
+
+    /// Adds `middleware` as the outermost layer.
+    pub fn push_front(&mut self, middleware: impl Middleware + 'static) {
+        self.layers.insert(0, Box::new(middleware));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+
+    pub fn inbound(&mut self, payload: Value) -> Option<Value> {
+        self.layers.iter_mut().try_fold(payload, |payload, layer| layer.inbound(payload))
+    }
+
+    pub fn outbound(&mut self, message: Value) -> Option<Value> {
+        self.layers.iter_mut().rev().try_fold(message, |message, layer| layer.outbound(message))
+    }
+}
+
+/// Prints every payload in both directions.
+pub struct LoggingMiddleware;
+
+impl Middleware for LoggingMiddleware {
+    fn inbound(&mut self, payload: Value) -> Option<Value> {
+        println!("<< {}", payload);
+        Some(payload)
+    }
+
+    fn outbound(&mut self, message: Value) -> Option<Value> {
+        println!(">> {}", message);
+        Some(message)
+    }
+}
+
+/// Drops inbound payloads for which the predicate returns false.
+pub struct FilterMiddleware<F>(pub F);
+
+impl<F: FnMut(&Value) -> bool + Send> Middleware for FilterMiddleware<F> {
+    fn inbound(&mut self, payload: Value) -> Option<Value> {
+        if (self.0)(&payload) { Some(payload) } else { None }
+    }
+}
+
+/// Rewrites payloads with a function per direction.
+pub struct RewriteMiddleware<I, O> {
+    pub inbound: I,
+    pub outbound: O,
+}
+
+impl<I, O> Middleware for RewriteMiddleware<I, O>
+where
+    I: FnMut(Value) -> Value + Send,
+    O: FnMut(Value) -> Value + Send,
+{
+    fn inbound(&mut self, payload: Value) -> Option<Value> {
+        Some((self.inbound)(payload))
+    }
+
+    fn outbound(&mut self, message: Value) -> Option<Value> {
+        Some((self.outbound)(message))
+    }
+}
+
+/// Only lets through inbound payloads whose `body.sender` is in the allowed set.
+/// Payloads without a sender (e.g. server responses) pass unchanged.
+pub struct AllowListMiddleware {
+    allowed: HashSet<String>,
+}
+
+impl AllowListMiddleware {
+    pub fn new(allowed: impl IntoIterator<Item = String>) -> Self {
+        AllowListMiddleware { allowed: allowed.into_iter().collect() }
+    }
+}
+
+impl Middleware for AllowListMiddleware {
+    fn inbound(&mut self, payload: Value) -> Option<Value> {
+        match payload["body"]["sender"].as_str() {
+            Some(sender) if !self.allowed.contains(sender) => {
+                println!("Dropping payload from unauthorized sender {}", sender);
+                None
+            }
+            _ => Some(payload),
+        }
+    }
+}
+
+/// Drops inbound payloads from senders exceeding a per-sender token bucket.
+pub struct RateLimitMiddleware {
+    capacity: u32,
+    refill_per_sec: f64,
+    buckets: HashMap<String, TokenBucket>,
+}
+
+impl RateLimitMiddleware {
+    pub fn new(capacity: u32, refill_per_sec: f64) -> Self {
+        RateLimitMiddleware {
+            capacity,
+            refill_per_sec,
+            buckets: HashMap::new(),
+        }
+    }
+}
+
+impl Middleware for RateLimitMiddleware {
+    fn inbound(&mut self, payload: Value) -> Option<Value> {
+        let sender = match payload["body"]["sender"].as_str() {
+            Some(sender) => sender.to_string(),
+            None => return Some(payload),
+        };
+        let (capacity, refill_per_sec) = (self.capacity, self.refill_per_sec);
+        let bucket = self.buckets.entry(sender.clone()).or_insert_with(|| TokenBucket::new(capacity, refill_per_sec));
+        if bucket.try_take() {
+            Some(payload)
+        } else {
+            println!("Rate limit exceeded for {}, dropping payload", sender);
+            None
+        }
+    }
+}
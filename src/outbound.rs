@@ -0,0 +1,281 @@
+use crate::rate_limit::{TokenBucket};
+use crate::transport::{Frame};
+
+use failure::{err_msg, format_err, Error};
+use serde_derive::{Serialize, Deserialize};
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// Send order of queued messages; higher priorities always go first.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum Priority {
+    Low,
+    Normal,
+    High,
+}
+
+impl Priority {
+    /// Logins are control traffic, chat messages are bulk, everything else sits in between.
+    pub fn of(message: &Value) -> Priority {
+        match message["type"].as_str() {
+            Some("service_login") | Some("user_login") => Priority::High,
+            Some("message_up") | Some("message_down") => Priority::Low,
+            _ => Priority::Normal,
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// What to do with a message when the queue is full.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum OverflowPolicy {
+    /// Discard the oldest queued message of the lowest non-empty priority.
+    DropOldest,
+    /// Discard the message being sent.
+    DropNewest,
+    /// Fail the send.
+    Error,
+    /// Wait, sending queued messages as the rate limit allows, until there is room.
+    #[default]
+    Block,
+}
+
+/// Settings for `OutboundQueue`, e.g. from `Config::outbound`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OutboundConfig {
+    pub capacity: usize,
+    /// Messages per second on average.
+    pub rate_per_sec: f64,
+    /// Messages that may be sent at once before the rate applies.
+    pub burst: u32,
+    #[serde(default)]
+    pub overflow: OverflowPolicy,
+}
+
+/// Result of `OutboundQueue::push`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Overflow {
+    /// The queue had room.
+    Queued,
+    /// This message was dropped.
+    Dropped,
+    /// An older message was dropped to make room.
+    Evicted,
+    /// Nothing was dropped; the message is handed back.
+    Full(Frame),
+}
+
+/// Outbound frames waiting for the rate limit, one FIFO per priority.
+#[derive(Debug)]
+pub struct OutboundQueue {
+    queues: [VecDeque<Frame>; 3],
+    capacity: usize,
+    overflow: OverflowPolicy,
+    bucket: TokenBucket,
+    dropped: u64,
+}
+
+impl OutboundQueue {
+    /// Fails unless `capacity` and `rate_per_sec` are positive: a queue that can hold
+    /// nothing or never refills would make senders wait forever.
+    pub fn new(capacity: usize, rate_per_sec: f64, burst: u32, overflow: OverflowPolicy) -> Result<Self, Error> {
+        if capacity == 0 {
+            return Err(err_msg("outbound queue capacity must be at least 1"));
+        }
+        if rate_per_sec.is_nan() || rate_per_sec <= 0.0 || rate_per_sec.is_infinite() {
+            return Err(format_err!("outbound rate_per_sec must be positive, got {}", rate_per_sec));
+        }
+        Ok(OutboundQueue {
+            queues: [VecDeque::new(), VecDeque::new(), VecDeque::new()],
+            capacity,
+            overflow,
+            bucket: TokenBucket::new(burst.max(1), rate_per_sec),
+            dropped: 0,
+        })
+    }
+
+    pub fn from_config(config: &OutboundConfig) -> Result<Self, Error> {
+        Self::new(config.capacity, config.rate_per_sec, config.burst, config.overflow)
+    }
+
+    pub fn len(&self) -> usize {
+        self.queues.iter().map(VecDeque::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len() >= self.capacity
+    }
+
+    pub fn overflow_policy(&self) -> OverflowPolicy {
+        self.overflow
+    }
+
+    /// Messages discarded because the queue was full.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Queues `frame`, applying the overflow policy if full. `Block` is handled by
+    /// the caller waiting for room, so here it behaves like `Error`.
+    pub fn push(&mut self, frame: Frame, priority: Priority) -> Overflow {
+        if !self.is_full() {
+            self.queues[priority.index()].push_back(frame);
+            return Overflow::Queued;
+        }
+        match self.overflow {
+            OverflowPolicy::DropNewest => {
+                self.dropped += 1;
+                Overflow::Dropped
+            }
+            OverflowPolicy::DropOldest => {
+                // Only evict something no more important than the new message.
+                let victim = (0..=priority.index()).find(|&i| !self.queues[i].is_empty());
+                match victim {
+                    Some(i) => {
+                        self.queues[i].pop_front();
+                        self.queues[priority.index()].push_back(frame);
+                        self.dropped += 1;
+                        Overflow::Evicted
+                    }
+                    None => {
+                        self.dropped += 1;
+                        Overflow::Dropped
+                    }
+                }
+            }
+            OverflowPolicy::Error | OverflowPolicy::Block => Overflow::Full(frame),
+        }
+    }
+
+    /// Takes the next frame and its priority if the rate limit allows sending it now.
+    pub fn pop_ready(&mut self) -> Option<(Frame, Priority)> {
+        if self.is_empty() || !self.bucket.try_take() {
+            return None;
+        }
+        let priorities = [Priority::Low, Priority::Normal, Priority::High];
+        priorities.iter().rev().find_map(|&priority| {
+            self.queues[priority.index()].pop_front().map(|frame| (frame, priority))
+        })
+    }
+
+    /// Takes a rate limit token for a frame sent around the queue, if one is available.
+    pub fn try_take_token(&mut self) -> bool {
+        self.bucket.try_take()
+    }
+
+    /// How long until a token is available, whether or not anything is queued.
+    pub fn token_wait_time(&mut self) -> Duration {
+        self.bucket.wait_time()
+    }
+
+    /// How long until `pop_ready` can return a frame; zero if the queue is empty.
+    pub fn wait_time(&mut self) -> Duration {
+        if self.is_empty() {
+            Duration::from_secs(0)
+        } else {
+            self.bucket.wait_time()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(text: &str) -> Frame {
+        Frame::Text(text.to_string())
+    }
+
+    fn queue(capacity: usize, overflow: OverflowPolicy) -> OutboundQueue {
+        OutboundQueue::new(capacity, 1000.0, 100, overflow).unwrap()
+    }
+
+    #[test]
+    fn rejects_zero_capacity_and_non_positive_rates() {
+        assert!(OutboundQueue::new(0, 1.0, 1, OverflowPolicy::Block).is_err());
+        assert!(OutboundQueue::new(1, 0.0, 1, OverflowPolicy::Block).is_err());
+        assert!(OutboundQueue::new(1, -1.0, 1, OverflowPolicy::Block).is_err());
+        assert!(OutboundQueue::new(1, f64::NAN, 1, OverflowPolicy::Block).is_err());
+        assert!(OutboundQueue::new(1, f64::INFINITY, 1, OverflowPolicy::Block).is_err());
+    }
+
+    #[test]
+    fn pops_higher_priorities_first_and_in_order_within_one() {
+        let mut queue = queue(10, OverflowPolicy::Block);
+        queue.push(frame("low"), Priority::Low);
+        queue.push(frame("normal 1"), Priority::Normal);
+        queue.push(frame("high"), Priority::High);
+        queue.push(frame("normal 2"), Priority::Normal);
+        let popped: Vec<_> = std::iter::from_fn(|| queue.pop_ready()).collect();
+        assert_eq!(popped, vec![
+            (frame("high"), Priority::High),
+            (frame("normal 1"), Priority::Normal),
+            (frame("normal 2"), Priority::Normal),
+            (frame("low"), Priority::Low),
+        ]);
+    }
+
+    #[test]
+    fn drop_newest_discards_the_new_message() {
+        let mut queue = queue(1, OverflowPolicy::DropNewest);
+        assert_eq!(queue.push(frame("first"), Priority::Low), Overflow::Queued);
+        assert_eq!(queue.push(frame("second"), Priority::High), Overflow::Dropped);
+        assert_eq!(queue.dropped(), 1);
+        assert_eq!(queue.pop_ready(), Some((frame("first"), Priority::Low)));
+    }
+
+    #[test]
+    fn drop_oldest_evicts_the_oldest_of_the_lowest_priority() {
+        let mut queue = queue(3, OverflowPolicy::DropOldest);
+        queue.push(frame("normal"), Priority::Normal);
+        queue.push(frame("low 1"), Priority::Low);
+        queue.push(frame("low 2"), Priority::Low);
+        assert_eq!(queue.push(frame("high"), Priority::High), Overflow::Evicted);
+        let popped: Vec<_> = std::iter::from_fn(|| queue.pop_ready()).map(|(frame, _)| frame).collect();
+        assert_eq!(popped, vec![frame("high"), frame("normal"), frame("low 2")]);
+    }
+
+    #[test]
+    fn drop_oldest_never_evicts_a_higher_priority() {
+        let mut queue = queue(2, OverflowPolicy::DropOldest);
+        queue.push(frame("high"), Priority::High);
+        queue.push(frame("normal"), Priority::Normal);
+        assert_eq!(queue.push(frame("low"), Priority::Low), Overflow::Dropped);
+        assert_eq!(queue.len(), 2);
+        assert_eq!(queue.dropped(), 1);
+    }
+
+    #[test]
+    fn error_and_block_hand_the_message_back() {
+        for &overflow in &[OverflowPolicy::Error, OverflowPolicy::Block] {
+            let mut queue = queue(1, overflow);
+            queue.push(frame("first"), Priority::Normal);
+            assert_eq!(queue.push(frame("second"), Priority::High), Overflow::Full(frame("second")));
+            assert_eq!(queue.len(), 1);
+            assert_eq!(queue.dropped(), 0);
+        }
+    }
+
+    #[test]
+    fn pop_ready_waits_for_the_rate_limit() {
+        let mut queue = OutboundQueue::new(10, 1.0, 2, OverflowPolicy::Block).unwrap();
+        for text in &["a", "b", "c"] {
+            queue.push(frame(text), Priority::Normal);
+        }
+        assert!(queue.pop_ready().is_some());
+        assert!(queue.pop_ready().is_some());
+        assert_eq!(queue.pop_ready(), None);
+        assert!(queue.wait_time() > Duration::from_millis(500));
+        assert_eq!(queue.len(), 1);
+    }
+}
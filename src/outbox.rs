@@ -0,0 +1,126 @@
+use crate::db::{MoobiusDatabase};
+use crate::transport::{Frame};
+
+use serde_derive::{Serialize, Deserialize};
+use std::collections::VecDeque;
+use std::io;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const OUTBOX_FIELD: &str = "outbox";
+
+/// Settings for `Outbox`, e.g. from `Config::outbox`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct OutboxConfig {
+    /// Messages older than this are dropped instead of sent; kept forever if not set.
+    #[serde(default)]
+    pub ttl_secs: Option<u64>,
+    /// Database file to persist the outbox to, so it survives restarts; in memory if not set.
+    /// Must not be `Config::db_path`, which `Moobius` loads and saves on its own.
+    #[serde(default)]
+    pub path: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct OutboxEntry {
+    frame: Frame,
+    /// Milliseconds since the Unix epoch.
+    queued_at: u64,
+}
+
+/// Outbound frames that could not be sent, held until the connection is back.
+#[derive(Debug)]
+pub struct Outbox {
+    entries: VecDeque<OutboxEntry>,
+    ttl: Option<Duration>,
+    db: Option<(MoobiusDatabase, String)>,
+    /// Changed since the last `save`.
+    dirty: bool,
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0)
+}
+
+impl Outbox {
+    pub fn in_memory(ttl: Option<Duration>) -> Self {
+        Outbox { entries: VecDeque::new(), ttl, db: None, dirty: false }
+    }
+
+    /// An outbox stored in the database file at `path`, picking up whatever a
+    /// previous run left there.
+    pub fn persisted(path: &str, ttl: Option<Duration>) -> io::Result<Self> {
+        let db = MoobiusDatabase::load(path)?;
+        let entries = match db.get_field(OUTBOX_FIELD) {
+            Some(value) => serde_json::from_value(value.clone())?,
+            None => VecDeque::new(),
+        };
+        Ok(Outbox { entries, ttl, db: Some((db, path.to_string())), dirty: false })
+    }
+
+    pub fn from_config(config: &OutboxConfig) -> io::Result<Self> {
+        let ttl = config.ttl_secs.map(Duration::from_secs);
+        match &config.path {
+            Some(path) => Self::persisted(path, ttl),
+            None => Ok(Self::in_memory(ttl)),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn push(&mut self, frame: Frame) {
+        self.entries.push_back(OutboxEntry { frame, queued_at: now_millis() });
+        self.dirty = true;
+    }
+
+    /// The oldest frame that has not expired, dropping expired ones on the way.
+    pub fn front(&mut self) -> Option<&Frame> {
+        if let Some(ttl) = self.ttl {
+            let now = now_millis();
+            let ttl = ttl.as_millis() as u64;
+            let before = self.entries.len();
+            self.entries.retain(|entry| now.saturating_sub(entry.queued_at) <= ttl);
+            if self.entries.len() < before {
+                println!("Outbox dropped {} expired messages", before - self.entries.len());
+                self.dirty = true;
+            }
+        }
+        self.entries.front().map(|entry| &entry.frame)
+    }
+
+    /// Removes the front frame once it has been sent.
+    pub fn pop_front(&mut self) -> Option<Frame> {
+        let entry = self.entries.pop_front();
+        self.dirty |= entry.is_some();
+        entry.map(|entry| entry.frame)
+    }
+
+    /// Writes changes to the database file, if the outbox is persisted. `WebSocket`
+    /// calls this once per flush rather than once per message.
+    pub fn save(&mut self) {
+        if !self.dirty {
+            return;
+        }
+        self.dirty = false;
+        if let Some((db, path)) = self.db.as_mut() {
+            match serde_json::to_value(&self.entries) {
+                Ok(value) => db.add_field(OUTBOX_FIELD, value),
+                Err(e) => println!("Error serializing outbox: {}", e),
+            }
+            if let Err(e) = db.save(path) {
+                println!("Error saving outbox to {}: {}", path, e);
+            }
+        }
+    }
+}
+
+impl Drop for Outbox {
+    fn drop(&mut self) {
+        self.save();
+    }
+}
@@ -0,0 +1,86 @@
+use std::time::{Duration, Instant};
+
+/// A token bucket: holds up to `capacity` tokens and refills at `refill_per_sec`.
+#[derive(Debug, Clone)]
+pub struct TokenBucket {
+    capacity: f64,
+    tokens: f64,
+    refill_per_sec: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    /// A full bucket allowing bursts of `capacity` and `refill_per_sec` on average.
+    pub fn new(capacity: u32, refill_per_sec: f64) -> Self {
+        TokenBucket {
+            capacity: capacity as f64,
+            tokens: capacity as f64,
+            refill_per_sec,
+            last_refill: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.refill_per_sec).min(self.capacity);
+        self.last_refill = now;
+    }
+
+    /// Takes a token if one is available.
+    pub fn try_take(&mut self) -> bool {
+        self.refill();
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// How long until a token is available; zero if one is available now.
+    pub fn wait_time(&mut self) -> Duration {
+        self.refill();
+        if self.tokens >= 1.0 || self.refill_per_sec <= 0.0 {
+            Duration::from_secs(0)
+        } else {
+            Duration::from_secs_f64((1.0 - self.tokens) / self.refill_per_sec)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allows_a_burst_of_capacity_then_refuses() {
+        let mut bucket = TokenBucket::new(3, 1.0);
+        assert!(bucket.try_take());
+        assert!(bucket.try_take());
+        assert!(bucket.try_take());
+        assert!(!bucket.try_take());
+    }
+
+    #[test]
+    fn wait_time_is_zero_with_a_token_and_the_refill_time_without() {
+        let mut bucket = TokenBucket::new(1, 2.0);
+        assert_eq!(bucket.wait_time(), Duration::from_secs(0));
+        assert!(bucket.try_take());
+        let wait = bucket.wait_time();
+        assert!(wait > Duration::from_millis(400) && wait <= Duration::from_millis(500), "{:?}", wait);
+    }
+
+    #[test]
+    fn refills_over_time_up_to_capacity() {
+        let mut bucket = TokenBucket::new(2, 100.0);
+        assert!(bucket.try_take());
+        assert!(bucket.try_take());
+        assert!(!bucket.try_take());
+        std::thread::sleep(Duration::from_millis(50));
+        // 50ms at 100/s is five tokens, capped at two.
+        assert!(bucket.try_take());
+        assert!(bucket.try_take());
+        assert!(!bucket.try_take());
+    }
+}
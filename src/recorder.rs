@@ -0,0 +1,75 @@
+use crate::middleware::{Middleware};
+
+use serde_derive::{Serialize, Deserialize};
+use serde_json::Value;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    In,
+    Out,
+}
+
+/// One line of a session recording.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RecordedMessage {
+    /// Milliseconds since the Unix epoch.
+    pub timestamp: u128,
+    pub direction: Direction,
+    pub message: Value,
+}
+
+/// Appends every inbound and outbound message to a JSONL file.
+/// Install it with `MiddlewareChain::push_front` so it sees traffic as it is on the wire.
+pub struct RecordingMiddleware {
+    writer: BufWriter<File>,
+}
+
+impl RecordingMiddleware {
+    pub fn create(path: &str) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(RecordingMiddleware { writer: BufWriter::new(file) })
+    }
+
+    fn record(&mut self, direction: Direction, message: &Value) {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis()).unwrap_or(0);
+        let line = RecordedMessage { timestamp, direction, message: message.clone() };
+        // Flush per line so a crash still leaves a usable recording.
+        let written = serde_json::to_writer(&mut self.writer, &line)
+            .map_err(io::Error::from)
+            .and_then(|_| self.writer.write_all(b"\n"))
+            .and_then(|_| self.writer.flush());
+        if let Err(e) = written {
+            println!("Failed to record message: {}", e);
+        }
+    }
+}
+
+impl Middleware for RecordingMiddleware {
+    fn inbound(&mut self, payload: Value) -> Option<Value> {
+        self.record(Direction::In, &payload);
+        Some(payload)
+    }
+
+    fn outbound(&mut self, message: Value) -> Option<Value> {
+        self.record(Direction::Out, &message);
+        Some(message)
+    }
+}
+
+/// Reads a recording made by `RecordingMiddleware`, skipping blank lines.
+pub fn read_recording(path: &str) -> io::Result<Vec<RecordedMessage>> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut messages = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        messages.push(serde_json::from_str(&line).map_err(io::Error::from)?);
+    }
+    Ok(messages)
+}
@@ -0,0 +1,74 @@
+use crate::types::{MessageContent};
+
+/// Who receives a relayed message.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RelayTarget {
+    /// Every member of the channel, including the sender.
+    All,
+    /// Every member of the channel except the sender.
+    Others,
+    /// Only these characters.
+    Characters(Vec<String>),
+}
+
+/// Who a relayed message appears to come from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RelaySender {
+    Original,
+    /// A (usually virtual) character speaking on the sender's behalf.
+    Character(String),
+}
+
+/// Rewrites or drops a message before it is relayed. Receives the original sender.
+pub type RelayTransform = Box<dyn Fn(&str, MessageContent) -> Option<MessageContent> + Send + Sync>;
+
+/// How `Moobius` forwards each message_up that is not a command to other channel members.
+pub struct RelayPolicy {
+    pub target: RelayTarget,
+    pub sender: RelaySender,
+    transform: Option<RelayTransform>,
+}
+
+impl RelayPolicy {
+    pub fn new(target: RelayTarget) -> Self {
+        RelayPolicy {
+            target,
+            sender: RelaySender::Original,
+            transform: None,
+        }
+    }
+
+    pub fn sender(mut self, sender: RelaySender) -> Self {
+        self.sender = sender;
+        self
+    }
+
+    /// Sets a hook that can rewrite each message, or return `None` to not relay it.
+    pub fn transform(mut self, transform: impl Fn(&str, MessageContent) -> Option<MessageContent> + Send + Sync + 'static) -> Self {
+        self.transform = Some(Box::new(transform));
+        self
+    }
+
+    pub fn apply_transform(&self, sender: &str, content: MessageContent) -> Option<MessageContent> {
+        match &self.transform {
+            Some(transform) => transform(sender, content),
+            None => Some(content),
+        }
+    }
+
+    pub fn sender_for(&self, original: &str) -> String {
+        match &self.sender {
+            RelaySender::Original => original.to_string(),
+            RelaySender::Character(id) => id.clone(),
+        }
+    }
+
+    /// Picks the recipients among `members` for a message from `sender`.
+    pub fn recipients(&self, sender: &str, members: Vec<String>) -> Vec<String> {
+        match &self.target {
+            RelayTarget::All => members,
+            RelayTarget::Others => members.into_iter().filter(|id| id != sender).collect(),
+            RelayTarget::Characters(ids) => ids.clone(),
+        }
+    }
+}
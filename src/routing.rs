@@ -0,0 +1,91 @@
+use crate::sdk::{Moobius};
+use crate::buttons::{ArgValue};
+use crate::context_menu::{MenuClick};
+
+use serde_json::Value;
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+pub type HandlerResult = Result<(), Box<dyn std::error::Error>>;
+pub type HandlerFuture<'a> = Pin<Box<dyn Future<Output = HandlerResult> + 'a>>;
+
+/// A registered handler. It borrows the `Moobius` client for as long as it runs,
+/// so it can send messages and update state.
+pub type Handler<E> = Arc<dyn for<'a> Fn(&'a mut Moobius, E) -> HandlerFuture<'a> + Send + Sync>;
+
+/// A `button_click` with its arguments parsed against the button's schema.
+#[derive(Debug, Clone)]
+pub struct ButtonClick {
+    pub channel_id: String,
+    pub button_id: String,
+    pub sender: String,
+    pub arguments: HashMap<String, ArgValue>,
+    pub body: Value,
+}
+
+impl ButtonClick {
+    pub fn arg(&self, name: &str) -> Option<&ArgValue> {
+        self.arguments.get(name)
+    }
+
+    pub fn text(&self, name: &str) -> Option<&str> {
+        self.arg(name).and_then(ArgValue::as_str)
+    }
+
+    pub fn number(&self, name: &str) -> Option<f64> {
+        self.arg(name).and_then(ArgValue::as_f64)
+    }
+}
+
+/// Maps a key such as a `button_id` to its handler.
+pub struct Router<E> {
+    handlers: HashMap<String, Handler<E>>,
+}
+
+pub type ButtonRouter = Router<ButtonClick>;
+pub type MenuRouter = Router<MenuClick>;
+
+impl<E> Router<E> {
+    pub fn new() -> Self {
+        Router {
+            handlers: HashMap::new(),
+        }
+    }
+
+    /// Registers `handler` for `key`, replacing any previous handler.
+    ///
+    /// ```ignore
+    /// moobius.button_router.on("message_btn", |moobius, click| Box::pin(async move {
+    ///     moobius.broadcast(&click.channel_id, &MessageContent::text("clicked")).await?;
+    ///     Ok(())
+    /// }));
+    /// ```
+    pub fn on<F>(&mut self, key: &str, handler: F)
+    where
+        F: for<'a> Fn(&'a mut Moobius, E) -> HandlerFuture<'a> + Send + Sync + 'static,
+    {
+        self.handlers.insert(key.to_string(), Arc::new(handler));
+    }
+
+    pub fn remove(&mut self, key: &str) {
+        self.handlers.remove(key);
+    }
+
+    /// Returns a handle to the handler for `key`. The handle is cloned out so the
+    /// router can be borrowed again while the handler runs.
+    pub fn get(&self, key: &str) -> Option<Handler<E>> {
+        self.handlers.get(key).cloned()
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &String> {
+        self.handlers.keys()
+    }
+}
+
+impl<E> Default for Router<E> {
+    fn default() -> Self {
+        Router::new()
+    }
+}
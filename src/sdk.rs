@@ -1,62 +1,408 @@
 use std::vec;
+use std::collections::HashMap;
 
 use crate::service_group_lib::{ServiceGroupLib};
 use crate::db::{MoobiusDatabase};
-use crate::socket::{WebSocket, Protocol, JsonProtocol};
+use crate::channel_members::{ChannelMembers};
+use crate::buttons::{Button, ButtonSource, parse_arguments};
+use crate::button_state::{ButtonState, ButtonChange};
+use crate::canvas::{CanvasItem, CanvasState};
+use crate::routing::{ButtonRouter, ButtonClick, MenuRouter};
+use crate::context_menu::{MenuItem, MenuClick};
+use crate::channel_info::{ChannelInfo, StyleItem};
+use crate::commands::{CommandRegistry, CommandMatch};
+use crate::relay::{RelayPolicy, RelayTarget};
+use crate::recorder::{RecordingMiddleware, Direction, read_recording};
+use crate::socket::{WebSocket, ProtocolKind};
+use crate::transport::{Transport, TungsteniteTransport, Frame};
+use crate::outbound::{OutboundQueue};
+use crate::outbox::{Outbox};
+use crate::dispatch::{Dispatcher};
+use crate::shutdown::{ShutdownHandle};
 use crate::http_api_wrapper::{HTTPAPIWrapper};
-use crate::types::{Config, MessageContent};
-use crate::Character;
+use crate::http_api::{HttpApi, FakeHttpApi};
+use crate::types::{Config, MessageContent, Recipients, SentMessage};
+use crate::text_utils::{LengthLimit};
 
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::Instant;
 use serde_json::{json, Value};
-use serde_derive::{Serialize, Deserialize};
 use failure::{err_msg, Error};
-use tungstenite::http::response;
 
 
 pub struct Moobius {
     pub config: Config,
-    pub http_client: HTTPAPIWrapper,
-    pub ws_client: WebSocket<JsonProtocol>,
+    /// Shared with task handlers.
+    pub http_client: Arc<dyn HttpApi>,
+    pub ws_client: WebSocket<ProtocolKind>,
     pub service_group_lib: ServiceGroupLib,
     pub db: MoobiusDatabase,
+    pub channel_members: ChannelMembers,
+    pub button_source: ButtonSource,
+    pub button_router: ButtonRouter,
+    pub button_state: ButtonState,
+    pub canvas_state: CanvasState,
+    pub context_menu: Vec<MenuItem>,
+    pub menu_router: MenuRouter,
+    pub channel_info: HashMap<String, ChannelInfo>,
+    pub style: HashMap<String, Vec<StyleItem>>,
+    pub commands: CommandRegistry,
+    pub relay: Option<RelayPolicy>,
+    /// Handlers that run on their own tasks instead of inside the listen loop.
+    pub dispatcher: Dispatcher,
+    shutdown: ShutdownHandle,
 }
 
 
 impl Moobius {
     pub async fn new(config: Config) -> Result<Self, Error> {
-        let http_client = HTTPAPIWrapper::new(config.clone());
-        let protocol = JsonProtocol;
-        let ws_client = WebSocket::connect(protocol, &config.ws_server_uri).await?;
+        let ws_client = WebSocket::connect(config.protocol, &config.ws_server_uri).await?;
+        let http_client = Arc::new(HTTPAPIWrapper::new(config.clone()));
+        Self::with_ws_client(config, ws_client, http_client)
+    }
+
+    /// A client without a network, e.g. for replaying recorded sessions. Outbound
+    /// messages pass through the middleware and are then discarded, and HTTP calls
+    /// go to a `FakeHttpApi`. Pass a prepared one to `with_http_api` if handlers
+    /// need e.g. channel members.
+    pub fn offline(config: Config) -> Result<Self, Error> {
+        let ws_client = WebSocket::detached(config.protocol);
+        Self::with_ws_client(config, ws_client, Arc::new(FakeHttpApi::new()))
+    }
+
+    /// A client whose WebSocket runs over `transport`, e.g. one end of
+    /// `InMemoryTransport::pair` so tests can drive handlers without a socket.
+    pub fn with_transport(config: Config, transport: impl Transport + 'static) -> Result<Self, Error> {
+        let ws_client = WebSocket::with_transport(config.protocol, transport);
+        let http_client = Arc::new(HTTPAPIWrapper::new(config.clone()));
+        Self::with_ws_client(config, ws_client, http_client)
+    }
+
+    /// Replaces the HTTP API client, e.g. with a `FakeHttpApi` in tests.
+    pub fn with_http_api(mut self, http_api: impl HttpApi + 'static) -> Self {
+        self.http_client = Arc::new(http_api);
+        self
+    }
+
+    fn with_ws_client(config: Config, ws_client: WebSocket<ProtocolKind>, http_client: Arc<dyn HttpApi>) -> Result<Self, Error> {
+        // Both would load the file at startup and overwrite each other's changes on save.
+        let outbox_path = config.outbox.as_ref().and_then(|outbox| outbox.path.as_ref());
+        if outbox_path.is_some() && outbox_path == config.db_path.as_ref() {
+            return Err(err_msg("outbox.path must not be the same file as db_path"));
+        }
+        let ws_client = match &config.outbound {
+            Some(outbound) => ws_client.with_outbound_queue(OutboundQueue::from_config(outbound)?),
+            None => ws_client,
+        };
+        let ws_client = match &config.outbox {
+            Some(outbox) => ws_client.with_outbox(Outbox::from_config(outbox)?),
+            None => ws_client,
+        };
         let service_group_lib = ServiceGroupLib::new();
-        let db = MoobiusDatabase::new();
+        let db = match &config.db_path {
+            Some(path) => MoobiusDatabase::load(path).unwrap_or_else(|e| {
+                println!("Error loading database from {}: {}", path, e);
+                MoobiusDatabase::new()
+            }),
+            None => MoobiusDatabase::new(),
+        };
+        let channel_members = ChannelMembers::new();
+        let button_source = match &config.buttons_path {
+            Some(path) => ButtonSource::file(path.as_str(), config.buttons_hot_reload),
+            None => ButtonSource::default(),
+        };
+        let dispatcher = Dispatcher::new(&config.concurrency);
         Ok(Self {
             config,
             http_client,
             ws_client,
             service_group_lib,
             db,
+            channel_members,
+            button_source,
+            button_router: ButtonRouter::new(),
+            button_state: ButtonState::new(),
+            canvas_state: CanvasState::new(),
+            context_menu: Vec::new(),
+            menu_router: MenuRouter::new(),
+            channel_info: HashMap::new(),
+            style: HashMap::new(),
+            commands: CommandRegistry::new(),
+            relay: None,
+            dispatcher,
+            shutdown: ShutdownHandle::new(),
         })
     }
 
+    /// Opens a new WebSocket connection and logs in again. Anything left in the
+    /// outbox is sent by `listen` once the server confirms the login.
+    pub async fn reconnect(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let transport = TungsteniteTransport::connect(&self.config.ws_server_uri).await?;
+        self.ws_client.replace_transport(transport);
+        let (access_token, _refresh_token) = self.http_client.authenticate()?;
+        let service_id = self.config.service_id.clone().ok_or("service_id is not set")?;
+        self.ws_client.service_login(&service_id, &access_token).await?;
+        Ok(())
+    }
+
+    /// Handles payloads as they arrive. Types with a handler on `dispatcher` run on
+    /// their own tasks; everything else is handled here, one payload at a time.
     pub async fn listen(&mut self) -> Result<(), Box<dyn std::error::Error>> {
         loop {
-            let message = self.ws_client.recv::<Value>().await?;
-            self.handle_received_payload(message).await?;
+            // select! polls its branches in random order, so a ready recv could win over shutdown.
+            if self.shutdown.is_shutdown() {
+                break;
+            }
+            // Wake up to send rate-limited messages even if nothing arrives.
+            let send_wait = self.ws_client.send_wait_time();
+            let send_due = async move {
+                match send_wait {
+                    Some(wait) => tokio::time::delay_for(wait).await,
+                    None => futures3::future::pending::<()>().await,
+                }
+            };
+            let ws_client = &mut self.ws_client;
+            let dispatcher = &mut self.dispatcher;
+            let shutdown = &self.shutdown;
+            let (message, outgoing) = tokio::select! {
+                _ = shutdown.requested() => break,
+                message = ws_client.recv::<Value>() => (Some(message?), None),
+                Some(frame) = dispatcher.next_outgoing() => (None, Some(frame)),
+                _ = send_due => (None, None),
+            };
+            if let Some(frame) = outgoing {
+                self.forward(frame).await?;
+            }
+            if let Some(message) = message.filter(|_| !self.shutdown.is_shutdown()) {
+                if let Some(message) = self.dispatcher.dispatch(&self.config, &self.http_client, &self.service_group_lib, message) {
+                    self.handle_received_payload(message).await?;
+                }
+            }
+            // Only what the rate limit allows now, so a burst doesn't hold up reading.
+            self.ws_client.send_pending().await?;
         }
+        let timeout = Duration::from_secs(self.config.shutdown_timeout_secs.unwrap_or(30));
+        self.shutdown(timeout).await
+    }
+
+    /// Returns a handle that makes `listen` stop and shut down gracefully.
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
+
+    /// Waits for running task handlers and sends everything still queued, for up to
+    /// `timeout` in total, then closes the WebSocket and saves the database to
+    /// `Config::db_path`. The database is saved even if an earlier step failed; the
+    /// first error is returned afterwards. Called by `listen` once shutdown is requested.
+    pub async fn shutdown(&mut self, timeout: Duration) -> Result<(), Box<dyn std::error::Error>> {
+        self.shutdown.shutdown();
+        let deadline = Instant::now() + timeout;
+        let handled = self.wait_for_handlers(Some(deadline)).await;
+        let flushed = match tokio::time::timeout_at(deadline, self.ws_client.flush()).await {
+            Ok(flushed) => flushed,
+            Err(_) => {
+                println!("Timed out sending queued messages");
+                Ok(())
+            }
+        };
+        let closed = self.ws_client.close().await;
+        let saved = match &self.config.db_path {
+            Some(path) => self.db.save(path),
+            None => Ok(()),
+        };
+        handled?;
+        flushed?;
+        closed?;
+        saved?;
+        Ok(())
+    }
+
+    /// Waits for task handlers to finish, sending what they produce along the way.
+    /// Gives up at `deadline`, if one is given.
+    async fn wait_for_handlers(&mut self, deadline: Option<Instant>) -> Result<(), Box<dyn std::error::Error>> {
+        while self.dispatcher.in_flight() > 0 {
+            let dispatcher = &mut self.dispatcher;
+            let idle = dispatcher.idle();
+            let timed_out = async move {
+                match deadline {
+                    Some(deadline) => tokio::time::delay_until(deadline).await,
+                    None => futures3::future::pending::<()>().await,
+                }
+            };
+            let outgoing = tokio::select! {
+                _ = idle => None,
+                Some(frame) = dispatcher.next_outgoing() => Some(frame),
+                _ = timed_out => {
+                    println!("Timed out waiting for handlers still running");
+                    break;
+                }
+            };
+            if let Some(frame) = outgoing {
+                self.forward(frame).await?;
+            }
+        }
+        while let Some(frame) = self.dispatcher.try_next_outgoing() {
+            self.forward(frame).await?;
+        }
+        Ok(())
+    }
+
+    /// Sends a frame produced by a task handler through this connection.
+    async fn forward(&mut self, frame: Frame) -> Result<(), Box<dyn std::error::Error>> {
+        if let Frame::Text(text) = frame {
+            // Task handlers encode with JsonProtocol.
+            let value: Value = serde_json::from_str(&text)?;
+            self.ws_client.send::<Value>(value).await?;
+        }
+        Ok(())
+    }
+
+    /// Appends every message of this session to a JSONL file at `path`.
+    pub fn record_to(&mut self, path: &str) -> std::io::Result<()> {
+        let recorder = RecordingMiddleware::create(path)?;
+        self.ws_client.middleware.push_front(recorder);
+        Ok(())
+    }
+
+    /// Feeds the inbound messages of a recording through the middleware and handlers,
+    /// in order, as if they had just been received.
+    pub async fn replay(&mut self, path: &str) -> Result<(), Box<dyn std::error::Error>> {
+        for recorded in read_recording(path)? {
+            if recorded.direction != Direction::In {
+                continue;
+            }
+            if let Some(payload) = self.ws_client.middleware.inbound(recorded.message) {
+                if let Some(payload) = self.dispatcher.dispatch(&self.config, &self.http_client, &self.service_group_lib, payload) {
+                    self.handle_received_payload(payload).await?;
+                }
+            }
+        }
+        self.wait_for_handlers(None).await?;
+        Ok(())
     }
+
     async fn on_update(&self, body: &Value) {
         println!("Received update: {:?}", body);
     }
-    async fn on_message_up(&self, body: &Value) {
+    async fn on_message_up(&mut self, body: &Value) {
         println!("Received message_up: {:?}", body);
+        let (channel_id, sender) = match (body["channel_id"].as_str(), body["sender"].as_str()) {
+            (Some(channel_id), Some(sender)) => (channel_id.to_string(), sender.to_string()),
+            _ => return,
+        };
+        let content = match MessageContent::from_body(body["subtype"].as_str().unwrap_or_default(), &body["content"]) {
+            Some(content) => content,
+            None => {
+                println!("Unsupported message_up content: {:?}", body["content"]);
+                return;
+            }
+        };
+        let command = content.as_text().and_then(|text| self.commands.parse(text, &channel_id, &sender, body));
+        match command {
+            Some(CommandMatch::Run(handler, invocation)) => {
+                let name = invocation.name.clone();
+                if let Err(e) = handler(self, invocation).await {
+                    println!("Error handling command {:?}: {:?}", name, e);
+                }
+            },
+            Some(CommandMatch::Denied(invocation)) => {
+                let reply = format!("You are not allowed to use \"{}\".", invocation.name);
+                let service_id = self.config.service_id.clone().unwrap_or_default();
+                if let Err(e) = self.send_text_message(reply, &channel_id, &service_id, Recipients::Characters(vec![sender]), LengthLimit::Unlimited).await {
+                    println!("Error sending permission denied reply: {:?}", e);
+                }
+            },
+            None => {
+                if let Err(e) = self.relay_message(&channel_id, &sender, content).await {
+                    println!("Error relaying message: {:?}", e);
+                }
+            },
+        }
+    }
+
+    /// Forwards a message from `sender` according to the relay policy, if one is set.
+    async fn relay_message(&mut self, channel_id: &str, sender: &str, content: MessageContent) -> Result<(), Box<dyn std::error::Error>> {
+        let needs_members = match &self.relay {
+            Some(policy) => !matches!(policy.target, RelayTarget::Characters(_)),
+            None => return Ok(()),
+        };
+        let members = if needs_members { self.fetch_channel_members(channel_id)? } else { Vec::new() };
+        let (recipients, relay_sender, content) = match &self.relay {
+            Some(policy) => match policy.apply_transform(sender, content) {
+                Some(content) => (policy.recipients(sender, members), policy.sender_for(sender), content),
+                None => return Ok(()),
+            },
+            None => return Ok(()),
+        };
+        if recipients.is_empty() {
+            return Ok(());
+        }
+        self.send_message(&content, channel_id, &relay_sender, Recipients::Characters(recipients)).await?;
+        Ok(())
     }
-    async fn on_fetch_playground(&self, body: &Value) {
+    async fn on_fetch_playground(&mut self, body: &Value) {
         println!("Received fetch_playground: {:?}", body);
+        let (channel_id, sender) = match (body["channel_id"].as_str(), body["sender"].as_str()) {
+            (Some(channel_id), Some(sender)) => (channel_id, sender),
+            _ => {
+                println!("Error: 'channel_id' or 'sender' not found in fetch_playground body");
+                return;
+            }
+        };
+        if !self.style.contains_key(channel_id) {
+            return;
+        }
+        if let Err(e) = self.send_style(channel_id, Recipients::Characters(vec![sender.to_string()])).await {
+            println!("Error sending style: {:?}", e);
+        }
     }
-    async fn on_fetch_channel_info(&self, body: &Value) {
+    async fn on_fetch_channel_info(&mut self, body: &Value) {
         println!("Received fetch_channel_info: {:?}", body);
+        let (channel_id, sender) = match (body["channel_id"].as_str(), body["sender"].as_str()) {
+            (Some(channel_id), Some(sender)) => (channel_id, sender),
+            _ => {
+                println!("Error: 'channel_id' or 'sender' not found in fetch_channel_info body");
+                return;
+            }
+        };
+        if !self.channel_info.contains_key(channel_id) {
+            return;
+        }
+        if let Err(e) = self.send_channel_info(channel_id, Recipients::Characters(vec![sender.to_string()])).await {
+            println!("Error sending channel info: {:?}", e);
+        }
+    }
+
+    /// Stores the info of `info.channel_id` and pushes it to the channel.
+    pub async fn set_channel_info(&mut self, info: ChannelInfo) -> Result<(), Box<dyn std::error::Error>> {
+        let channel_id = info.channel_id.clone();
+        self.channel_info.insert(channel_id.clone(), info);
+        self.send_channel_info(&channel_id, Recipients::Channel).await
+    }
+
+    pub async fn send_channel_info(&mut self, channel_id: &str, recipients: Recipients) -> Result<(), Box<dyn std::error::Error>> {
+        let info = self.channel_info.get(channel_id).cloned().ok_or_else(|| format!("No channel info set for {}", channel_id))?;
+        let group_recipients = self.resolve_recipients(channel_id, recipients).await?;
+        let service_id = self.config.service_id.clone().ok_or("service_id is not configured")?;
+        self.ws_client.update_channel_info(&service_id, &info, &group_recipients).await?;
+        Ok(())
     }
-    async fn create_character(&mut self, file_path: &str, name: &str, description: &str) {
+
+    /// Stores the playground style of `channel_id` and pushes it to the channel.
+    pub async fn set_style(&mut self, channel_id: &str, style: Vec<StyleItem>) -> Result<(), Box<dyn std::error::Error>> {
+        self.style.insert(channel_id.to_string(), style);
+        self.send_style(channel_id, Recipients::Channel).await
+    }
+
+    pub async fn send_style(&mut self, channel_id: &str, recipients: Recipients) -> Result<(), Box<dyn std::error::Error>> {
+        let style = self.style.get(channel_id).cloned().ok_or_else(|| format!("No style set for {}", channel_id))?;
+        let group_recipients = self.resolve_recipients(channel_id, recipients).await?;
+        let service_id = self.config.service_id.clone().ok_or("service_id is not configured")?;
+        self.ws_client.update_style(&service_id, channel_id, &style, &group_recipients).await?;
+        Ok(())
+    }
+    pub async fn create_character(&mut self, file_path: &str, name: &str, description: &str) {
         let avatar_url = self.http_client.upload_file(file_path);
         if !self.db.has_field("virtual_characters") {
             self.db.add_field("virtual_characters", Value::Array(vec![]));
@@ -70,8 +416,16 @@ impl Moobius {
         }
     }
 
-    async fn on_fetch_characters(&mut self, body: &Value) {
+    /// Sends the current character list, including virtual characters, to the channel.
+    pub async fn refresh_characters(&mut self) {
+        self.on_fetch_characters(&json!({})).await;
+    }
+
+    async fn on_fetch_characters(&mut self, _body: &Value) {
         let real_character_ids: Result<Vec<String>, Box<dyn std::error::Error>> = self.http_client.fetch_real_characters(self.config.clone().channels[0].as_str(), self.config.clone().service_id.as_ref().unwrap());
+        if let Ok(ids) = &real_character_ids {
+            self.channel_members.set(self.config.channels[0].as_str(), ids.clone());
+        }
         if self.db.has_field("virtual_characters") {
             let virtual_character_ids: Vec<Value> = self.db.get_field("virtual_characters").unwrap().as_array().unwrap().clone();
             let mut virtual_character_ids: Vec<String> = virtual_character_ids.into_iter()
@@ -79,42 +433,171 @@ impl Moobius {
                 .collect();
             let mut total_character_list: Vec<String> = real_character_ids.unwrap();
             total_character_list.append(&mut virtual_character_ids);
-            let group_character_ids = self.service_group_lib.convert_list(&self.http_client, total_character_list, true, None).await.unwrap();
-            let response = self.ws_client.update_character_list(self.config.service_id.as_ref().unwrap(), self.config.channels[0].as_str(), group_character_ids.as_str(), group_character_ids.as_str()).await;
+            let group_character_ids = self.service_group_lib.convert_list(self.http_client.as_ref(), total_character_list, true, None).await.unwrap();
+            let _ = self.ws_client.update_character_list(self.config.service_id.as_ref().unwrap(), self.config.channels[0].as_str(), group_character_ids.as_str(), group_character_ids.as_str()).await;
 
         } else {
             let total_character_list: Vec<String> = real_character_ids.unwrap();
-            let group_character_ids = self.service_group_lib.convert_list(&self.http_client, total_character_list, true, None).await.unwrap();
-            let response = self.ws_client.update_character_list(self.config.service_id.as_ref().unwrap(), self.config.channels[0].as_str(), group_character_ids.as_str(), group_character_ids.as_str()).await;
+            let group_character_ids = self.service_group_lib.convert_list(self.http_client.as_ref(), total_character_list, true, None).await.unwrap();
+            let _ = self.ws_client.update_character_list(self.config.service_id.as_ref().unwrap(), self.config.channels[0].as_str(), group_character_ids.as_str(), group_character_ids.as_str()).await;
         }
     }
 
+    pub fn set_button_source(&mut self, button_source: ButtonSource) {
+        self.button_source = button_source;
+    }
+
     async fn on_fetch_buttons(&mut self, body: &Value) {
-        let button_list_str = std::fs::read_to_string("src/buttons.json").unwrap();
-        let button_list: Vec<Value> = serde_json::from_str(&button_list_str).unwrap();
-        let channel_id = body["channel_id"].as_str().unwrap();
-        let sender = body["sender"].as_str().unwrap();
-        let group_recipients = self.service_group_lib.convert_list(&self.http_client, vec![sender.to_string()], true, None).await.unwrap();
-        let response = self.ws_client.update_buttons(self.config.service_id.as_ref().unwrap(), channel_id, button_list, group_recipients.as_str()).await;
+        let (channel_id, sender) = match (body["channel_id"].as_str(), body["sender"].as_str()) {
+            (Some(channel_id), Some(sender)) => (channel_id, sender),
+            _ => {
+                println!("Error: 'channel_id' or 'sender' not found in fetch_buttons body");
+                return;
+            }
+        };
+        if let Err(e) = self.send_buttons(channel_id, sender).await {
+            println!("Error sending buttons: {:?}", e);
+        }
+    }
+
+    /// Sends `user_id` the buttons they currently see: the button source, with any
+    /// changes made for them applied on top.
+    pub async fn send_buttons(&mut self, channel_id: &str, user_id: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let buttons = self.button_source.buttons_for(channel_id, user_id)?;
+        self.button_state.sync(channel_id, user_id, buttons);
+        let buttons = self.button_state.visible(channel_id, user_id).unwrap_or_default();
+        let group_recipients = self.service_group_lib.convert_list(self.http_client.as_ref(), vec![user_id.to_string()], true, None).await?;
+        let service_id = self.config.service_id.clone().ok_or("service_id is not configured")?;
+        self.ws_client.update_buttons(&service_id, channel_id, &buttons, &group_recipients).await?;
+        Ok(())
+    }
+
+    /// Applies `changes` to the buttons of each recipient and pushes the result to them.
+    /// Recipients whose buttons end up identical share a single update_buttons message.
+    pub async fn update_user_buttons(&mut self, channel_id: &str, recipients: Recipients, changes: &[ButtonChange]) -> Result<(), Box<dyn std::error::Error>> {
+        let user_ids = match recipients {
+            Recipients::Characters(ids) => ids,
+            Recipients::Channel => self.fetch_channel_members(channel_id)?,
+            Recipients::Group(group_id) => self.service_group_lib.group_members(&group_id).await
+                .ok_or_else(|| format!("Unknown group {}", group_id))?,
+        };
+        let mut by_buttons: Vec<(Vec<Button>, Vec<String>)> = Vec::new();
+        for user_id in user_ids {
+            let buttons = self.button_source.buttons_for(channel_id, &user_id)?;
+            self.button_state.sync(channel_id, &user_id, buttons);
+            let buttons = self.button_state.apply(channel_id, &user_id, changes).unwrap_or_default();
+            match by_buttons.iter_mut().find(|(b, _)| *b == buttons) {
+                Some((_, users)) => users.push(user_id),
+                None => by_buttons.push((buttons, vec![user_id])),
+            }
+        }
+        let service_id = self.config.service_id.clone().ok_or("service_id is not configured")?;
+        for (buttons, users) in by_buttons {
+            let group_recipients = self.service_group_lib.convert_list(self.http_client.as_ref(), users, true, None).await?;
+            self.ws_client.update_buttons(&service_id, channel_id, &buttons, &group_recipients).await?;
+        }
+        Ok(())
     }
     
     async fn on_fetch_canvas(&mut self, body: &Value) {
         println!("Received fetch_canvas: {:?}", body);
+        let (channel_id, sender) = match (body["channel_id"].as_str(), body["sender"].as_str()) {
+            (Some(channel_id), Some(sender)) => (channel_id, sender),
+            _ => {
+                println!("Error: 'channel_id' or 'sender' not found in fetch_canvas body");
+                return;
+            }
+        };
+        if let Err(e) = self.send_canvas(channel_id, sender).await {
+            println!("Error sending canvas: {:?}", e);
+        }
+    }
+
+    /// Sends `user_id` the canvas they currently see.
+    pub async fn send_canvas(&mut self, channel_id: &str, user_id: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let items = self.canvas_state.canvas_for(channel_id, user_id);
+        let group_recipients = self.service_group_lib.convert_list(self.http_client.as_ref(), vec![user_id.to_string()], true, None).await?;
+        let service_id = self.config.service_id.clone().ok_or("service_id is not configured")?;
+        self.ws_client.update_canvas(&service_id, channel_id, &items, &group_recipients).await?;
+        Ok(())
+    }
+
+    /// Sets the canvas of `channel_id` and pushes it to every member who has no override of their own.
+    pub async fn set_canvas(&mut self, channel_id: &str, items: Vec<CanvasItem>) -> Result<(), Box<dyn std::error::Error>> {
+        self.canvas_state.set_channel(channel_id, items.clone());
+        let followers: Vec<String> = self.fetch_channel_members(channel_id)?
+            .into_iter()
+            .filter(|id| self.canvas_state.follows_channel(channel_id, id))
+            .collect();
+        if followers.is_empty() {
+            return Ok(());
+        }
+        let group_recipients = self.service_group_lib.convert_list(self.http_client.as_ref(), followers, true, None).await?;
+        let service_id = self.config.service_id.clone().ok_or("service_id is not configured")?;
+        self.ws_client.update_canvas(&service_id, channel_id, &items, &group_recipients).await?;
+        Ok(())
+    }
+
+    /// Gives `user_id` their own canvas in `channel_id` and pushes it to them.
+    pub async fn set_user_canvas(&mut self, channel_id: &str, user_id: &str, items: Vec<CanvasItem>) -> Result<(), Box<dyn std::error::Error>> {
+        self.canvas_state.set_user(channel_id, user_id, items);
+        self.send_canvas(channel_id, user_id).await
     }
 
     async fn on_fetch_context_menu(&mut self, body: &Value) {
         println!("Received fetch_context_menu: {:?}", body);
+        let (channel_id, sender) = match (body["channel_id"].as_str(), body["sender"].as_str()) {
+            (Some(channel_id), Some(sender)) => (channel_id, sender),
+            _ => {
+                println!("Error: 'channel_id' or 'sender' not found in fetch_context_menu body");
+                return;
+            }
+        };
+        if let Err(e) = self.send_context_menu(channel_id, Recipients::Characters(vec![sender.to_string()])).await {
+            println!("Error sending context menu: {:?}", e);
+        }
+    }
+
+    /// Replaces the context menu and pushes it to every member of the configured channels.
+    pub async fn set_context_menu(&mut self, items: Vec<MenuItem>) -> Result<(), Box<dyn std::error::Error>> {
+        self.context_menu = items;
+        for channel_id in self.config.channels.clone() {
+            self.send_context_menu(&channel_id, Recipients::Channel).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn send_context_menu(&mut self, channel_id: &str, recipients: Recipients) -> Result<(), Box<dyn std::error::Error>> {
+        let group_recipients = self.resolve_recipients(channel_id, recipients).await?;
+        let service_id = self.config.service_id.clone().ok_or("service_id is not configured")?;
+        let items = self.context_menu.clone();
+        self.ws_client.update_context_menu(&service_id, channel_id, &items, &group_recipients).await?;
+        Ok(())
+    }
+
+    async fn on_join_channel(&mut self, body: &Value) {
+        if let (Some(channel_id), Some(sender)) = (body["channel_id"].as_str(), body["sender"].as_str()) {
+            self.channel_members.join(channel_id, sender);
+        }
+    }
+
+    async fn on_leave_channel(&mut self, body: &Value) {
+        if let (Some(channel_id), Some(sender)) = (body["channel_id"].as_str(), body["sender"].as_str()) {
+            self.channel_members.leave(channel_id, sender);
+        }
     }
 
     async fn on_action(&mut self, body: &Value) {
         println!("Received action: {:?}", body);
         match body["subtype"].as_str() {
-            Some("fetch_playground") => self.on_fetch_playground(&body).await,
-            Some("fetch_channel_info") => self.on_fetch_channel_info(&body).await,
-            Some("fetch_characters") => self.on_fetch_characters(&body).await,
-            Some("fetch_buttons") => self.on_fetch_buttons(&body).await,
-            Some("fetch_canvas") => self.on_fetch_canvas(&body).await,
-            Some("fetch_context_menu") => self.on_fetch_context_menu(&body).await,
+            Some("fetch_playground") => self.on_fetch_playground(body).await,
+            Some("fetch_channel_info") => self.on_fetch_channel_info(body).await,
+            Some("fetch_characters") => self.on_fetch_characters(body).await,
+            Some("fetch_buttons") => self.on_fetch_buttons(body).await,
+            Some("fetch_canvas") => self.on_fetch_canvas(body).await,
+            Some("fetch_context_menu") => self.on_fetch_context_menu(body).await,
+            Some("join_channel") => self.on_join_channel(body).await,
+            Some("leave_channel") => self.on_leave_channel(body).await,
             _ => {
                 println!("Unknown action subtype: {:?}", body["subtype"]);
             },
@@ -145,67 +628,69 @@ impl Moobius {
                 return;
             }
         };
-    
-        let to_whom = match self.http_client.fetch_real_characters(&channel_id, self.config.service_id.as_ref().unwrap()) {
-            Ok(result) => result,
-            Err(e) => {
-                println!("Error fetching real characters: {:?}", e);
+
+        // Prefer what the user actually sees, which includes buttons added just for them.
+        let visible = self.button_state.visible(&channel_id, &who_clicked)
+            .and_then(|buttons| buttons.into_iter().find(|b| b.button_id == button_id));
+        let button = match visible {
+            Some(button) => Some(button),
+            None => match self.button_source.buttons_for(&channel_id, &who_clicked) {
+                Ok(buttons) => buttons.into_iter().find(|b| b.button_id == button_id),
+                Err(e) => {
+                    println!("Error loading buttons: {}", e);
+                    None
+                }
+            },
+        };
+        let handler = match self.button_router.get(&button_id) {
+            Some(handler) => handler,
+            None => {
+                println!("Unknown button_id: {}", button_id);
                 return;
             }
         };
-        
-        let value = body["arguments"].get(0)
-                                     .and_then(|arg| arg["value"].as_str())
-                                     .map(|v| v.to_lowercase());
-    
-        // Handle button click based on button_id
-        match button_id.as_str() {
-            "message_btn" => {
-                match value.as_deref() {
-                    Some("text") => {
-                        let some_text: String = "Hello, World!".to_string();
-                        self.send_text_message(some_text, &channel_id, &who_clicked, to_whom, 1000).await;
-                    },
-                    Some("image") => {
-                        let cat_in_plastic_bag = "src/cat_plastic_bag.png";
-                        self.send_image_message(cat_in_plastic_bag, &channel_id, &who_clicked, to_whom).await;
-                    },
-                    _ => {
-                        println!("Unknown value message_btn: {:?}", value);
-                    }
-                }
-            },
-            "user_btn" => {
-                match value.as_deref() {
-                    Some("make mickey") => {
-                        let new_mickey = self.create_character("src/mickey.png", "Mickey", "A friendly mouse").await;
-                        let empty_body = json!({});
-                        self.on_fetch_characters(&empty_body).await;
-                    },
-                    Some("mickey talk") => {
-                        let last_mickey_id = self.db.get_field("virtual_characters").unwrap().as_array().unwrap().last().unwrap().get("character_id").unwrap().as_str().unwrap().to_string();
-                        self.send_text_message("M-I-C-K-E-Y M-O-U-S-E!".to_string(), &channel_id, &last_mickey_id, vec![who_clicked], 1000).await;
-                    },
-                    _ => {
-                        println!("Unknown value user_btn: {:?}", value);
-                    }
+        let arguments = match parse_arguments(button.as_ref(), &body["arguments"]) {
+            Ok(arguments) => arguments,
+            Err(e) => {
+                let reply = format!("Invalid input for {}: {}", button.as_ref().map_or(button_id.as_str(), |b| b.button_name.as_str()), e);
+                let sender = self.config.service_id.clone().unwrap_or_default();
+                if let Err(e) = self.send_text_message(reply, &channel_id, &sender, Recipients::Characters(vec![who_clicked]), LengthLimit::Unlimited).await {
+                    println!("Error sending argument validation error: {:?}", e);
                 }
-            },
-            "command_btn" => {
-                let cmds = "
-                \"show\" (send to service): Show buttons and canvas.
-                \"hide\" (send to service): Hide buttons and canvas.
-                \"reset\" (send to service): Reset Mickeys and refresh buttons.
-                ".trim().replace('\n', "\n\n");
-                self.send_text_message(cmds, &channel_id, &who_clicked, vec![who_clicked.clone()], 1000).await;
-            },
-            _ => {
-                println!("Unknown button_id: {}", button_id);
+                return;
             }
+        };
+        let click = ButtonClick {
+            channel_id,
+            button_id: button_id.clone(),
+            sender: who_clicked,
+            arguments,
+            body: body.clone(),
+        };
+        if let Err(e) = handler(self, click).await {
+            println!("Error handling button click {}: {:?}", button_id, e);
         }
     }
-    async fn on_context_menu_click(&self, body: &Value) {
+    async fn on_context_menu_click(&mut self, body: &Value) {
         println!("Received context_menu_click: {:?}", body);
+        let click = match MenuClick::from_body(body) {
+            Some(click) => click,
+            None => {
+                println!("Error: 'channel_id', 'item_id' or 'sender' not found in menu_click body");
+                return;
+            }
+        };
+        let handler = match self.menu_router.get(&click.item_id) {
+            Some(handler) => handler,
+            None => {
+                println!("Unknown context menu item_id: {}", click.item_id);
+                return;
+            }
+        };
+        let item_id = click.item_id.clone();
+        if let Err(e) = handler(self, click).await {
+            println!("Error handling context menu click {}: {:?}", item_id, e);
+        }
     }
     async fn on_copy_client(&self, body: &Value) {
         println!("Received copy_client: {:?}", body);
@@ -228,44 +713,87 @@ impl Moobius {
         }
         Ok(())
     }
-    async fn send_text_message(
+    /// Resolves `recipients` to a group ID that can be used as a message_down recipient.
+    pub async fn resolve_recipients(&mut self, channel_id: &str, recipients: Recipients) -> Result<String, Box<dyn std::error::Error>> {
+        let character_ids = match recipients {
+            Recipients::Group(group_id) => return Ok(group_id),
+            Recipients::Characters(character_ids) => character_ids,
+            Recipients::Channel => self.fetch_channel_members(channel_id)?,
+        };
+        self.service_group_lib.convert_list(self.http_client.as_ref(), character_ids, true, None).await
+    }
+
+    /// Returns the real characters in `channel_id`, fetching them over HTTP only if they are not cached yet.
+    pub fn fetch_channel_members(&mut self, channel_id: &str) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        if let Some(ids) = self.channel_members.get(channel_id) {
+            return Ok(ids.clone());
+        }
+        let ids = self.http_client.fetch_real_characters(channel_id, self.config.service_id.as_ref().ok_or("service_id is not configured")?)?;
+        self.channel_members.set(channel_id, ids.clone());
+        Ok(ids)
+    }
+
+    /// Sends `content` from the service to every member of `channel_id`.
+    pub async fn broadcast(&mut self, channel_id: &str, content: &MessageContent) -> Result<SentMessage, Box<dyn std::error::Error>> {
+        let sender = self.config.service_id.clone().ok_or("service_id is not configured")?;
+        self.send_message(content, channel_id, &sender, Recipients::Channel).await
+    }
+
+    /// Sends `content` as `sender` to `recipients` in `channel_id`.
+    pub async fn send_message(
         &mut self,
-        the_message: String,
+        content: &MessageContent,
         channel_id: &str,
         sender: &str,
-        recipients: Vec<String>,
-        len_limit: usize,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        let mut content = the_message;
+        recipients: Recipients,
+    ) -> Result<SentMessage, Box<dyn std::error::Error>> {
+        let group_recipients = self.resolve_recipients(channel_id, recipients).await?;
+        self.send_message_to_group(content, channel_id, sender, &group_recipients).await
+    }
 
-        if content.len() > len_limit {
-            content = self.limit_len(&content, len_limit);
+    async fn send_message_to_group(
+        &mut self,
+        content: &MessageContent,
+        channel_id: &str,
+        sender: &str,
+        group_id: &str,
+    ) -> Result<SentMessage, Box<dyn std::error::Error>> {
+        if group_id.is_empty() {
+            return Err("No recipients to send the message to".into());
         }
-
-        let group_recipients = self.service_group_lib.convert_list(&self.http_client, recipients, true, None).await?;
-        let response = self.ws_client.message_down( self.config.service_id.as_ref().unwrap(), &channel_id, &group_recipients, "text", &content, &sender).await?;
-        Ok(())
+        let service_id = self.config.service_id.clone().ok_or("service_id is not configured")?;
+        let message = self.ws_client.message_down(&service_id, channel_id, group_id, content, sender).await?;
+        SentMessage::new(channel_id, group_id, message)
     }
 
-    async fn send_image_message(
+    /// Sends a text message, applying `len_limit`. A split message yields one `SentMessage` per part, in order.
+    pub async fn send_text_message(
         &mut self,
-        file_path: &str,
+        the_message: String,
         channel_id: &str,
         sender: &str,
-        recipients: Vec<String>,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        let avatar_url = self.http_client.upload_file(file_path);
-        let group_recipients = self.service_group_lib.convert_list(&self.http_client, recipients, true, None).await?;
-        let response = self.ws_client.message_down( self.config.service_id.as_ref().unwrap(), &channel_id, &group_recipients, "image", avatar_url.unwrap().as_str(), &sender).await?;
-        Ok(())
+        recipients: Recipients,
+        len_limit: LengthLimit,
+    ) -> Result<Vec<SentMessage>, Box<dyn std::error::Error>> {
+        let parts = len_limit.apply(&the_message)?;
+        let group_recipients = self.resolve_recipients(channel_id, recipients).await?;
+        let mut sent = Vec::new();
+        for content in parts {
+            sent.push(self.send_message_to_group(&MessageContent::text(content), channel_id, sender, &group_recipients).await?);
+        }
+        Ok(sent)
     }
 
-    fn limit_len(&self, text: &str, len: usize) -> String {
-        if text.len() > len {
-            text[..len].to_string()
-        } else {
-            text.to_string()
-        }
+    /// Uploads the file at `file_path` and sends it as an image message.
+    pub async fn send_image_message(
+        &mut self,
+        file_path: &str,
+        channel_id: &str,
+        sender: &str,
+        recipients: Recipients,
+    ) -> Result<SentMessage, Box<dyn std::error::Error>> {
+        let image_url = self.http_client.upload_file(file_path)?;
+        self.send_message(&MessageContent::image(image_url), channel_id, sender, recipients).await
     }
 
 }
\ No newline at end of file
@@ -1,10 +1,11 @@
-use crate::http_api_wrapper::HTTPAPIWrapper;
+use crate::http_api::HttpApi;
 
 use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
-#[derive(Debug)]
+/// Clones share their groups, e.g. with task handlers.
+#[derive(Debug, Clone)]
 pub struct ServiceGroupLib {
     id2ids_mdown: Arc<Mutex<HashMap<String, Vec<String>>>>,
     ids2id_mdown: Arc<Mutex<HashMap<String, String>>>,
@@ -12,6 +13,12 @@ pub struct ServiceGroupLib {
     ids2id_mup: Arc<Mutex<HashMap<String, String>>>,
 }
 
+impl Default for ServiceGroupLib {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl ServiceGroupLib {
     pub fn new() -> Self {
         Self {
@@ -22,9 +29,14 @@ impl ServiceGroupLib {
         }
     }
 
+    /// Returns the character IDs behind a message_down group created by `convert_list`.
+    pub async fn group_members(&self, group_id: &str) -> Option<Vec<String>> {
+        self.id2ids_mdown.lock().await.get(group_id).cloned()
+    }
+
     pub async fn convert_list(
         &self,
-        http_api: &HTTPAPIWrapper,
+        http_api: &dyn HttpApi,
         character_ids: Vec<String>,
         is_message_down: bool,
         channel_id: Option<String>,
@@ -66,7 +78,7 @@ impl ServiceGroupLib {
 // async fn main() {
 //     // Example usage
 //     // let lib = ServiceGroupLib::new();
-//     // let http_api = MockHttpApi {}; // Assuming a mock implementation of HttpApi trait
+//     // let http_api = FakeHttpApi::new();
 //     // let character_ids = vec!["id1".to_string(), "id2".to_string()];
 //     // match lib.convert_list(&http_api, character_ids, true, None).await {
 //     //     Ok(group_id) => println!("Group ID: {}", group_id),
@@ -0,0 +1,54 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::sync::Notify;
+
+struct ShutdownState {
+    requested: AtomicBool,
+    notify: Notify,
+}
+
+/// Asks a running `Moobius::listen` to stop. Clones share the same state, so one
+/// can be moved into a signal handler task.
+///
+/// ```ignore
+/// let shutdown = moobius.shutdown_handle();
+/// tokio::spawn(async move {
+///     tokio::signal::ctrl_c().await.ok();
+///     shutdown.shutdown();
+/// });
+/// moobius.listen().await?;
+/// ```
+#[derive(Clone)]
+pub struct ShutdownHandle {
+    inner: Arc<ShutdownState>,
+}
+
+impl ShutdownHandle {
+    pub fn new() -> Self {
+        ShutdownHandle {
+            inner: Arc::new(ShutdownState { requested: AtomicBool::new(false), notify: Notify::new() }),
+        }
+    }
+
+    pub fn shutdown(&self) {
+        self.inner.requested.store(true, Ordering::SeqCst);
+        self.inner.notify.notify();
+    }
+
+    pub fn is_shutdown(&self) -> bool {
+        self.inner.requested.load(Ordering::SeqCst)
+    }
+
+    /// Completes once `shutdown` has been called.
+    pub async fn requested(&self) {
+        while !self.is_shutdown() {
+            self.inner.notify.notified().await;
+        }
+    }
+}
+
+impl Default for ShutdownHandle {
+    fn default() -> Self {
+        ShutdownHandle::new()
+    }
+}
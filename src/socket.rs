@@ -1,25 +1,28 @@
-#![feature(async_await, async_closure)]
-use crate::types::{Config};
+use crate::types::{MessageContent};
+use crate::buttons::{Button};
+use crate::canvas::{CanvasItem};
+use crate::context_menu::{MenuItem};
+use crate::channel_info::{ChannelInfo, StyleItem};
+use crate::middleware::{MiddlewareChain};
+use crate::transport::{Transport, TungsteniteTransport, NullTransport, Frame};
+use crate::outbound::{OutboundQueue, OverflowPolicy, Priority, Overflow};
+use crate::outbox::{Outbox};
 
 use failure::{err_msg, Error};
-use futures::Stream as _;
-use futures3::compat::{Future01CompatExt, Sink01CompatExt, Stream01CompatExt};
-use futures3::{Sink, SinkExt, Stream, StreamExt};
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use std::borrow::Borrow;
-use std::pin::Pin;
-use tokio_tungstenite::connect_async;
-use tungstenite::error::Error as WsError;
-use tungstenite::Message;
-use url::Url;
-use log::{info, error};
 use uuid::Uuid;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 pub trait Protocol {
     fn serialize(&self, obj: &impl Serialize) -> Result<Vec<u8>, Error>;
     fn deserialize<T: for <'de> Deserialize<'de>>(&self, data: &[u8]) -> Result<T, Error>;
+
+    /// Whether serialized messages are sent as binary frames rather than UTF-8 text frames.
+    fn is_binary(&self) -> bool {
+        false
+    }
 }
 
 pub struct JsonProtocol;
@@ -34,54 +37,361 @@ impl Protocol for JsonProtocol {
     }
 }
 
+/// MessagePack encoding, sent as binary frames. Requires the `msgpack` feature.
+#[cfg(feature = "msgpack")]
+pub struct MessagePackProtocol;
+
+#[cfg(feature = "msgpack")]
+impl Protocol for MessagePackProtocol {
+    fn serialize(&self, obj: &impl Serialize) -> Result<Vec<u8>, Error> {
+        // Named fields, so the other side can decode into maps like it does JSON objects.
+        rmp_serde::to_vec_named(obj).map_err(Error::from)
+    }
+
+    fn deserialize<T: for <'de> Deserialize<'de>>(&self, data: &[u8]) -> Result<T, Error> {
+        rmp_serde::from_slice(data).map_err(Error::from)
+    }
+
+    fn is_binary(&self) -> bool {
+        true
+    }
+}
+
+/// CBOR encoding, sent as binary frames. Requires the `cbor` feature.
+#[cfg(feature = "cbor")]
+pub struct CborProtocol;
+
+#[cfg(feature = "cbor")]
+impl Protocol for CborProtocol {
+    fn serialize(&self, obj: &impl Serialize) -> Result<Vec<u8>, Error> {
+        serde_cbor::to_vec(obj).map_err(Error::from)
+    }
+
+    fn deserialize<T: for <'de> Deserialize<'de>>(&self, data: &[u8]) -> Result<T, Error> {
+        serde_cbor::from_slice(data).map_err(Error::from)
+    }
+
+    fn is_binary(&self) -> bool {
+        true
+    }
+}
+
+/// Selects a protocol at runtime, e.g. from `Config::protocol`.
+#[derive(serde_derive::Serialize, serde_derive::Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ProtocolKind {
+    #[default]
+    Json,
+    #[cfg(feature = "msgpack")]
+    #[serde(rename = "msgpack")]
+    MessagePack,
+    #[cfg(feature = "cbor")]
+    Cbor,
+}
+
+impl Protocol for ProtocolKind {
+    fn serialize(&self, obj: &impl Serialize) -> Result<Vec<u8>, Error> {
+        match self {
+            ProtocolKind::Json => JsonProtocol.serialize(obj),
+            #[cfg(feature = "msgpack")]
+            ProtocolKind::MessagePack => MessagePackProtocol.serialize(obj),
+            #[cfg(feature = "cbor")]
+            ProtocolKind::Cbor => CborProtocol.serialize(obj),
+        }
+    }
+
+    fn deserialize<T: for <'de> Deserialize<'de>>(&self, data: &[u8]) -> Result<T, Error> {
+        match self {
+            ProtocolKind::Json => JsonProtocol.deserialize(data),
+            #[cfg(feature = "msgpack")]
+            ProtocolKind::MessagePack => MessagePackProtocol.deserialize(data),
+            #[cfg(feature = "cbor")]
+            ProtocolKind::Cbor => CborProtocol.deserialize(data),
+        }
+    }
+
+    fn is_binary(&self) -> bool {
+        match self {
+            ProtocolKind::Json => JsonProtocol.is_binary(),
+            #[cfg(feature = "msgpack")]
+            ProtocolKind::MessagePack => MessagePackProtocol.is_binary(),
+            #[cfg(feature = "cbor")]
+            ProtocolKind::Cbor => CborProtocol.is_binary(),
+        }
+    }
+}
+
 pub struct WebSocket<T: Protocol> {
     protocol: T,
-    sink: Pin<Box<dyn Sink<Message, Error = WsError> + Send>>,
-    stream: Pin<Box<dyn Stream<Item = Result<Message, WsError>> + Send>>
+    pub middleware: MiddlewareChain,
+    transport: Box<dyn Transport>,
+    /// When set, sends are paced through this queue instead of going straight to the transport.
+    outbound: Option<OutboundQueue>,
+    /// When set, messages that fail to send are kept here until `flush_outbox`.
+    outbox: Option<Outbox>,
+    /// A login was sent and the server has not sent anything since.
+    login_pending: bool,
+    /// A login was confirmed and the outbox has not been sent since.
+    outbox_ready: bool,
 }
 
 impl<T: Protocol> WebSocket<T> {
     pub async fn connect(protocol: T, url: impl AsRef<str>) -> Result<Self, Error> {
-        let url = Url::parse(url.as_ref())?;
-        let (ws_stream, _) = connect_async(url).compat().await?;
-        let (sink, stream) = ws_stream.split();
-        let (sink, stream) = (sink.sink_compat(), stream.compat());
-        let (sink, stream) = (Box::pin(sink), Box::pin(stream));
-        Ok(Self { protocol, sink, stream })
+        let transport = TungsteniteTransport::connect(url.as_ref()).await?;
+        Ok(Self::with_transport(protocol, transport))
+    }
+
+    pub fn with_transport(protocol: T, transport: impl Transport + 'static) -> Self {
+        Self { protocol, middleware: MiddlewareChain::new(), transport: Box::new(transport), outbound: None, outbox: None, login_pending: false, outbox_ready: false }
     }
 
+    /// A socket that is not connected to anything: sent messages still pass through
+    /// the middleware but are then discarded, and `recv` reports the stream as ended.
+    pub fn detached(protocol: T) -> Self {
+        Self::with_transport(protocol, NullTransport)
+    }
+
+    /// Rate-limits and prioritizes outbound messages through `queue`.
+    pub fn with_outbound_queue(mut self, queue: OutboundQueue) -> Self {
+        self.outbound = Some(queue);
+        self
+    }
+
+    pub fn outbound_queue(&self) -> Option<&OutboundQueue> {
+        self.outbound.as_ref()
+    }
+
+    /// Buffers messages in `outbox` instead of failing when the connection is down.
+    pub fn with_outbox(mut self, outbox: Outbox) -> Self {
+        self.outbox = Some(outbox);
+        self
+    }
+
+    pub fn outbox(&self) -> Option<&Outbox> {
+        self.outbox.as_ref()
+    }
+
+    /// Swaps in a new connection, e.g. after the old one dropped. Anything in the
+    /// outbox is sent by `flush` or `send_pending` once a login on it succeeds.
+    pub fn replace_transport(&mut self, transport: impl Transport + 'static) {
+        self.transport = Box::new(transport);
+    }
+
+    /// Sends at the priority `Priority::of` assigns to the message.
     pub async fn send<REQ: Serialize>(&mut self, value: impl Borrow<REQ>) -> Result<(), Error> {
-        let data = self.protocol.serialize(value.borrow())?;
-        // Convert the byte vector to a UTF-8 string
-        let message_string = String::from_utf8(data).map_err(Error::from)?;
-        // Create a text WebSocket message
-        let msg = Message::Text(message_string);
-        // Send the text message through the WebSocket
-        self.sink.send(msg).await?;
-        Ok(())
+        let value = serde_json::to_value(value.borrow())?;
+        let priority = Priority::of(&value);
+        self.send_with_priority(value, priority).await
     }
 
-    pub async fn recv<RESP: for <'de> Deserialize<'de>>(&mut self) -> Result<RESP, Error> {
+    /// Without an outbound queue this sends immediately. With one, the message is
+    /// queued and whatever the rate limit allows right now is sent; the rest goes
+    /// out on later sends or `flush`.
+    pub async fn send_with_priority(&mut self, value: Value, priority: Priority) -> Result<(), Error> {
+        let value = match self.middleware.outbound(value) {
+            Some(value) => value,
+            None => return Ok(()),
+        };
+        let data = self.protocol.serialize(&value)?;
+        let frame = if self.protocol.is_binary() {
+            Frame::Binary(data)
+        } else {
+            // Text protocols produce UTF-8 and go out as text frames
+            Frame::Text(String::from_utf8(data).map_err(Error::from)?)
+        };
+        // Stay behind messages already waiting in the outbox; logins go ahead so it can be flushed.
+        if priority != Priority::High {
+            if let Some(outbox) = self.outbox.as_mut().filter(|outbox| !outbox.is_empty()) {
+                outbox.push(frame);
+                return Ok(());
+            }
+        }
+        if self.outbound.is_none() {
+            return self.write_frame(frame, priority).await;
+        }
+        self.enqueue(frame, priority).await?;
+        self.send_ready().await
+    }
+
+    async fn enqueue(&mut self, mut frame: Frame, priority: Priority) -> Result<(), Error> {
         loop {
-            let msg = self.stream.next().await;
-            let msg = msg.ok_or_else(|| err_msg("websocket stream ended"))??;
-            match msg {
-                Message::Text(text) => {
-                    let value = self.protocol.deserialize(text.as_bytes())?;
-                    return Ok(value);
+            let queue = self.outbound.as_mut().expect("outbound queue");
+            match queue.push(frame, priority) {
+                Overflow::Queued | Overflow::Evicted => return Ok(()),
+                Overflow::Dropped => {
+                    println!("Outbound queue full, dropped message");
+                    return Ok(());
+                }
+                Overflow::Full(returned) => {
+                    if queue.overflow_policy() != OverflowPolicy::Block {
+                        return Err(err_msg("outbound queue is full"));
+                    }
+                    // Backpressure: wait until the next queued message can go out.
+                    self.send_next().await?;
+                    frame = returned;
                 }
-                Message::Binary(data) => {
-                    let value = self.protocol.deserialize(&data)?;
-                    return Ok(value);
+            }
+        }
+    }
+
+    /// Sends what the rate limit allows right now, without waiting: the outbox first
+    /// if a login was confirmed, then the outbound queue. Also writes the outbox to
+    /// disk if messages were buffered in it.
+    pub async fn send_pending(&mut self) -> Result<(), Error> {
+        self.send_confirmed_outbox(false).await;
+        let result = self.send_ready().await;
+        self.save_outbox();
+        result
+    }
+
+    /// How long until `send_pending` can send something; `None` if nothing is waiting.
+    pub fn send_wait_time(&mut self) -> Option<Duration> {
+        let outbox_waiting = self.outbox_ready && self.outbox.as_ref().is_some_and(|outbox| !outbox.is_empty());
+        let queued = self.outbound.as_ref().is_some_and(|queue| !queue.is_empty());
+        if !outbox_waiting && !queued {
+            return None;
+        }
+        Some(self.outbound.as_mut().map_or(Duration::from_secs(0), OutboundQueue::token_wait_time))
+    }
+
+    /// Sends queued messages while the rate limit allows, without waiting.
+    async fn send_ready(&mut self) -> Result<(), Error> {
+        while let Some((frame, priority)) = self.outbound.as_mut().and_then(OutboundQueue::pop_ready) {
+            self.write_frame(frame, priority).await?;
+        }
+        Ok(())
+    }
+
+    /// Waits for the rate limit and sends the highest-priority queued message.
+    async fn send_next(&mut self) -> Result<(), Error> {
+        let queue = match self.outbound.as_mut() {
+            Some(queue) => queue,
+            None => return Ok(()),
+        };
+        tokio::time::delay_for(queue.wait_time()).await;
+        if let Some((frame, priority)) = queue.pop_ready() {
+            self.write_frame(frame, priority).await?;
+        }
+        Ok(())
+    }
+
+    /// Sends `frame`, keeping it in the outbox if that fails. Logins are never
+    /// buffered: resending one later with a stale token would be useless.
+    async fn write_frame(&mut self, frame: Frame, priority: Priority) -> Result<(), Error> {
+        if self.outbox.is_none() || priority == Priority::High {
+            return self.transport.send_frame(frame).await;
+        }
+        if let Err(e) = self.transport.send_frame(frame.clone()).await {
+            println!("Send failed, keeping message in outbox: {}", e);
+            if let Some(outbox) = self.outbox.as_mut() {
+                outbox.push(frame);
+            }
+        }
+        Ok(())
+    }
+
+    /// Sends the outbox in order, paced by the outbound queue's rate limit if there
+    /// is one. Expired messages are dropped. If a send fails the outbox is left as it
+    /// is for the next attempt.
+    pub async fn flush_outbox(&mut self) -> Result<(), Error> {
+        let result = self.send_outbox(true).await;
+        self.save_outbox();
+        result
+    }
+
+    /// Sends the outbox if a login was confirmed since it was last emptied. Errors
+    /// are only reported: the outbox keeps the messages for the next login.
+    async fn send_confirmed_outbox(&mut self, wait: bool) {
+        if !self.outbox_ready {
+            return;
+        }
+        match self.send_outbox(wait).await {
+            Ok(()) => self.outbox_ready = self.outbox.as_ref().is_some_and(|outbox| !outbox.is_empty()),
+            Err(e) => {
+                self.outbox_ready = false;
+                println!("Error flushing outbox: {}", e);
+            }
+        }
+    }
+
+    /// Sends the outbox in order. Without `wait`, stops when the rate limit runs out.
+    async fn send_outbox(&mut self, wait: bool) -> Result<(), Error> {
+        loop {
+            let frame = match self.outbox.as_mut().and_then(Outbox::front) {
+                Some(frame) => frame.clone(),
+                None => return Ok(()),
+            };
+            if let Some(queue) = self.outbound.as_mut() {
+                while !queue.try_take_token() {
+                    if !wait {
+                        return Ok(());
+                    }
+                    tokio::time::delay_for(queue.token_wait_time()).await;
                 }
-                Message::Ping(_) | Message::Pong(_) => {}
-                Message::Close(_) => {
+            }
+            self.transport.send_frame(frame).await?;
+            if let Some(outbox) = self.outbox.as_mut() {
+                outbox.pop_front();
+            }
+        }
+    }
+
+    /// Sends everything in the outbound queue, waiting for the rate limit as needed.
+    /// If a login was confirmed, the outbox is sent first.
+    /// Also writes the outbox to disk if messages were buffered in it.
+    pub async fn flush(&mut self) -> Result<(), Error> {
+        self.send_confirmed_outbox(true).await;
+        let result = async {
+            while self.outbound.as_ref().is_some_and(|queue| !queue.is_empty()) {
+                self.send_next().await?;
+            }
+            Ok(())
+        }.await;
+        self.save_outbox();
+        result
+    }
+
+    fn save_outbox(&mut self) {
+        if let Some(outbox) = self.outbox.as_mut() {
+            outbox.save();
+        }
+    }
+
+    /// Waits for the next message the inbound middleware lets through. Safe to
+    /// cancel, e.g. in `select!`, as long as the transport's `recv_frame` is.
+    pub async fn recv<RESP: for <'de> Deserialize<'de>>(&mut self) -> Result<RESP, Error> {
+        loop {
+            let frame = self.transport.recv_frame().await?;
+            let frame = frame.ok_or_else(|| err_msg("websocket stream ended"))?;
+            let value: Value = match frame {
+                Frame::Text(text) => self.protocol.deserialize(text.as_bytes())?,
+                Frame::Binary(data) => self.protocol.deserialize(&data)?,
+                Frame::Close => {
                     return Err(err_msg("wsbsocket closed"));
                 }
+            };
+            // Nothing is awaited from here on, so a frame is never lost when the
+            // caller drops this future, e.g. in `select!`.
+            if self.login_pending {
+                // The server drops connections that fail to log in, so hearing
+                // from it means the login went through. The outbox goes out on the next
+                // `flush` or `send_pending`.
+                self.login_pending = false;
+                self.outbox_ready = true;
+            }
+            if let Some(value) = self.middleware.inbound(value) {
+                return Ok(serde_json::from_value(value)?);
             }
         }
     }
 
+    /// Sends a close frame and shuts down the transport. Queued messages are not
+    /// sent; call `flush` first for that.
+    pub async fn close(&mut self) -> Result<(), Error> {
+        self.save_outbox();
+        self.transport.close().await
+    }
+
     pub async fn service_login(&mut self, service_id: &str, access_token: &str) -> Result<Value, Box<dyn std::error::Error>> {
         let message = json!({
             "type": "service_login",
@@ -92,6 +402,76 @@ impl<T: Protocol> WebSocket<T> {
         });
 
         println!("service_login message: {:?}", message);
+        self.send(message.clone()).await?;
+        // The outbox is sent once the server confirms the login; see `recv`.
+        self.login_pending = self.outbox.is_some();
+
+        Ok(message)
+    }
+
+    pub async fn user_login(&mut self, access_token: &str) -> Result<Value, Box<dyn std::error::Error>> {
+        let message = json!({
+            "type": "user_login",
+            "request_id": Uuid::new_v4().to_string(),
+            "auth_origin": "cognito",
+            "access_token": access_token,
+        });
+
+        self.send(message.clone()).await?;
+        // The outbox is sent once the server confirms the login; see `recv`.
+        self.login_pending = self.outbox.is_some();
+
+        Ok(message)
+    }
+
+    pub async fn join_channel(&mut self, user_id: &str, channel_id: &str) -> Result<Value, Box<dyn std::error::Error>> {
+        self.user_action(user_id, "join_channel", channel_id).await
+    }
+
+    pub async fn leave_channel(&mut self, user_id: &str, channel_id: &str) -> Result<Value, Box<dyn std::error::Error>> {
+        self.user_action(user_id, "leave_channel", channel_id).await
+    }
+
+    /// Sends a user-side `action` such as `join_channel` or `fetch_buttons`.
+    pub async fn user_action(&mut self, user_id: &str, subtype: &str, channel_id: &str) -> Result<Value, Box<dyn std::error::Error>> {
+        let message = json!({
+            "type": "action",
+            "request_id": Uuid::new_v4().to_string(),
+            "user_id": user_id,
+            "body": {
+                "subtype": subtype,
+                "channel_id": channel_id,
+                "context": {}
+            }
+        });
+
+        self.send(message.clone()).await?;
+
+        Ok(message)
+    }
+
+    pub async fn button_click(
+        &mut self,
+        user_id: &str,
+        channel_id: &str,
+        button_id: &str,
+        arguments: &[(String, String)]
+    ) -> Result<Value, Box<dyn std::error::Error>> {
+        let arguments: Vec<Value> = arguments.iter()
+            .map(|(name, value)| json!({"name": name, "value": value}))
+            .collect();
+        let message = json!({
+            "type": "button_click",
+            "request_id": Uuid::new_v4().to_string(),
+            "user_id": user_id,
+            "body": {
+                "button_id": button_id,
+                "channel_id": channel_id,
+                "arguments": arguments,
+                "context": {}
+            }
+        });
+
         self.send(message.clone()).await?;
 
         Ok(message)
@@ -125,11 +505,11 @@ impl<T: Protocol> WebSocket<T> {
         &mut self,
         service_id: &str,
         channel_id: &str,
-        buttons: Vec<Value>,
+        buttons: &[Button],
         recipients: &str
     ) -> Result<Value, Box<dyn std::error::Error>> {
 
-        let button_dicts: Vec<Value> = buttons.iter().map(|b| b.clone()).collect();
+        let button_dicts: Vec<Value> = buttons.iter().map(serde_json::to_value).collect::<Result<_, _>>()?;
         let message = json!({
             "type": "update",
             "request_id": Uuid::new_v4().to_string(),
@@ -139,7 +519,6 @@ impl<T: Protocol> WebSocket<T> {
                 "channel_id": channel_id,
                 "recipients": recipients,
                 "content": button_dicts,
-                "group_id": "temp",
                 "context": {}
             }
         });
@@ -148,14 +527,108 @@ impl<T: Protocol> WebSocket<T> {
         Ok(message)
     }
 
+    pub async fn update_canvas(
+        &mut self,
+        service_id: &str,
+        channel_id: &str,
+        items: &[CanvasItem],
+        recipients: &str
+    ) -> Result<Value, Box<dyn std::error::Error>> {
+        let message = json!({
+            "type": "update",
+            "request_id": Uuid::new_v4().to_string(),
+            "service_id": service_id,
+            "body": {
+                "subtype": "update_canvas",
+                "channel_id": channel_id,
+                "recipients": recipients,
+                "content": items,
+                "context": {}
+            }
+        });
+
+        self.send(message.clone()).await?;
+        Ok(message)
+    }
+
+    pub async fn update_context_menu(
+        &mut self,
+        service_id: &str,
+        channel_id: &str,
+        items: &[MenuItem],
+        recipients: &str
+    ) -> Result<Value, Box<dyn std::error::Error>> {
+        let message = json!({
+            "type": "update",
+            "request_id": Uuid::new_v4().to_string(),
+            "service_id": service_id,
+            "body": {
+                "subtype": "update_context_menu",
+                "channel_id": channel_id,
+                "recipients": recipients,
+                "content": items,
+                "context": {}
+            }
+        });
+
+        self.send(message.clone()).await?;
+        Ok(message)
+    }
+
+    pub async fn update_channel_info(
+        &mut self,
+        service_id: &str,
+        info: &ChannelInfo,
+        recipients: &str
+    ) -> Result<Value, Box<dyn std::error::Error>> {
+        let message = json!({
+            "type": "update",
+            "request_id": Uuid::new_v4().to_string(),
+            "service_id": service_id,
+            "body": {
+                "subtype": "update_channel_info",
+                "channel_id": info.channel_id,
+                "recipients": recipients,
+                "content": info,
+                "context": {}
+            }
+        });
+
+        self.send(message.clone()).await?;
+        Ok(message)
+    }
+
+    pub async fn update_style(
+        &mut self,
+        service_id: &str,
+        channel_id: &str,
+        style: &[StyleItem],
+        recipients: &str
+    ) -> Result<Value, Box<dyn std::error::Error>> {
+        let message = json!({
+            "type": "update",
+            "request_id": Uuid::new_v4().to_string(),
+            "service_id": service_id,
+            "body": {
+                "subtype": "update_style",
+                "channel_id": channel_id,
+                "recipients": recipients,
+                "content": style,
+                "context": {}
+            }
+        });
+
+        self.send(message.clone()).await?;
+        Ok(message)
+    }
+
     pub async fn message_up(
         &mut self,
         user_id: &str,
         service_id: &str,
         channel_id: &str,
-        recipients: &[&str],
-        subtype: &str,
-        content: &Value
+        recipients: &str,
+        content: &MessageContent
     ) -> Result<Value, Box<dyn std::error::Error>> {
         if recipients.is_empty() {
             return Ok(json!(null));
@@ -169,7 +642,7 @@ impl<T: Protocol> WebSocket<T> {
             "user_id": user_id,
             "service_id": service_id,
             "body": {
-                "subtype": subtype,
+                "subtype": content.subtype(),
                 "channel_id": channel_id,
                 "content": content,
                 "recipients": recipients,
@@ -188,8 +661,7 @@ impl<T: Protocol> WebSocket<T> {
         service_id: &str,
         channel_id: &str,
         recipients: &str,
-        subtype: &str,
-        content: &str,
+        content: &MessageContent,
         sender: &str
     ) -> Result<Value, Box<dyn std::error::Error>> {
         if recipients.is_empty() {
@@ -203,9 +675,9 @@ impl<T: Protocol> WebSocket<T> {
             "request_id": Uuid::new_v4().to_string(),
             "service_id": service_id,
             "body": {
-                "subtype": subtype,
+                "subtype": content.subtype(),
                 "channel_id": channel_id,
-                "content": json!({"text": content, "path": content}),
+                "content": content,
                 "recipients": recipients,
                 "timestamp": timestamp,
                 "sender": sender,
@@ -0,0 +1,281 @@
+//! An in-process stand-in for the Moobius HTTP and WebSocket servers, for testing
+//! services offline. Enabled with the `testing` cargo feature.
+//!
+//! ```ignore
+//! let server = MockServer::start()?;
+//! server.set_channel_characters("channel-1", vec!["user-1".to_string()]);
+//! let mut moobius = Moobius::new(server.config("service-1", vec!["channel-1".to_string()])).await?;
+//! server.inject(json!({"type": "action", "body": {"subtype": "fetch_buttons", "channel_id": "channel-1", "sender": "user-1"}}));
+//! let update = server.wait_for_message(|m| m["body"]["subtype"] == "update_buttons", Duration::from_secs(5));
+//! ```
+
+use crate::types::{Config};
+use crate::socket::{ProtocolKind};
+use crate::dispatch::{ConcurrencyConfig};
+
+use serde_json::{json, Value};
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tungstenite::Message;
+
+/// An HTTP request received by the mock server.
+#[derive(Debug, Clone)]
+pub struct RecordedRequest {
+    pub method: String,
+    pub path: String,
+    pub query: HashMap<String, String>,
+    /// The JSON body, or `Value::Null` for empty and non-JSON bodies.
+    pub body: Value,
+}
+
+#[derive(Default)]
+struct MockState {
+    http_requests: Vec<RecordedRequest>,
+    ws_received: Vec<Value>,
+    ws_pending: VecDeque<Value>,
+    characters: HashMap<String, Vec<String>>,
+    next_id: u64,
+}
+
+impl MockState {
+    fn next_id(&mut self, prefix: &str) -> String {
+        self.next_id += 1;
+        format!("{}-{}", prefix, self.next_id)
+    }
+}
+
+pub struct MockServer {
+    pub http_uri: String,
+    pub ws_uri: String,
+    state: Arc<Mutex<MockState>>,
+}
+
+impl MockServer {
+    /// Binds both servers to free local ports and starts serving on background threads.
+    pub fn start() -> io::Result<Self> {
+        let http_listener = TcpListener::bind("127.0.0.1:0")?;
+        let ws_listener = TcpListener::bind("127.0.0.1:0")?;
+        let http_uri = format!("http://{}", http_listener.local_addr()?);
+        let ws_uri = format!("ws://{}", ws_listener.local_addr()?);
+        let state = Arc::new(Mutex::new(MockState::default()));
+
+        let http_state = state.clone();
+        let upload_base = http_uri.clone();
+        thread::spawn(move || {
+            for stream in http_listener.incoming().flatten() {
+                let state = http_state.clone();
+                let upload_base = upload_base.clone();
+                thread::spawn(move || {
+                    if let Err(e) = serve_http(stream, &state, &upload_base) {
+                        println!("Mock HTTP connection error: {}", e);
+                    }
+                });
+            }
+        });
+
+        let ws_state = state.clone();
+        thread::spawn(move || {
+            for stream in ws_listener.incoming().flatten() {
+                let state = ws_state.clone();
+                thread::spawn(move || serve_ws(stream, &state));
+            }
+        });
+
+        Ok(MockServer { http_uri, ws_uri, state })
+    }
+
+    /// A `Config` pointing at this server.
+    pub fn config(&self, service_id: &str, channels: Vec<String>) -> Config {
+        Config {
+            http_server_uri: self.http_uri.clone(),
+            ws_server_uri: self.ws_uri.clone(),
+            email: "mock@example.com".to_string(),
+            password: "mock-password".to_string(),
+            service_id: Some(service_id.to_string()),
+            channels,
+            buttons_path: None,
+            buttons_hot_reload: false,
+            protocol: ProtocolKind::Json,
+            outbound: None,
+            outbox: None,
+            concurrency: ConcurrencyConfig::default(),
+            db_path: None,
+            shutdown_timeout_secs: None,
+        }
+    }
+
+    /// Sets what `/channel/character_list` returns for `channel_id`.
+    pub fn set_channel_characters(&self, channel_id: &str, character_ids: Vec<String>) {
+        self.state.lock().unwrap().characters.insert(channel_id.to_string(), character_ids);
+    }
+
+    /// Queues `payload` to be sent to the connected WebSocket client.
+    pub fn inject(&self, payload: Value) {
+        self.state.lock().unwrap().ws_pending.push_back(payload);
+    }
+
+    pub fn http_requests(&self) -> Vec<RecordedRequest> {
+        self.state.lock().unwrap().http_requests.clone()
+    }
+
+    /// Every message the WebSocket client has sent so far.
+    pub fn received_messages(&self) -> Vec<Value> {
+        self.state.lock().unwrap().ws_received.clone()
+    }
+
+    /// Polls until a received WebSocket message matches `predicate` or `timeout` passes.
+    pub fn wait_for_message(&self, predicate: impl Fn(&Value) -> bool, timeout: Duration) -> Option<Value> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(found) = self.state.lock().unwrap().ws_received.iter().find(|m| predicate(m)) {
+                return Some(found.clone());
+            }
+            if Instant::now() >= deadline {
+                return None;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+}
+
+fn serve_http(stream: TcpStream, state: &Mutex<MockState>, upload_base: &str) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let target = parts.next().unwrap_or_default().to_string();
+
+    let mut content_length = 0;
+    let mut chunked = false;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+            break;
+        }
+        let (name, value) = line.split_at(line.find(':').unwrap_or(line.len()));
+        let value = value.trim_start_matches(':').trim();
+        match name.to_lowercase().as_str() {
+            "content-length" => content_length = value.parse().unwrap_or(0),
+            "transfer-encoding" => chunked = value.eq_ignore_ascii_case("chunked"),
+            _ => {}
+        }
+    }
+    let body = if chunked { read_chunked(&mut reader)? } else {
+        let mut body = vec![0; content_length];
+        reader.read_exact(&mut body)?;
+        body
+    };
+
+    let (path, query) = match target.find('?') {
+        Some(i) => (&target[..i], parse_query(&target[i + 1..])),
+        None => (target.as_str(), HashMap::new()),
+    };
+    let path = format!("/{}", path.trim_start_matches('/'));
+    let body = serde_json::from_slice(&body).unwrap_or(Value::Null);
+    let request = RecordedRequest { method, path, query, body };
+    let (status, response) = route(&request, state, upload_base);
+    state.lock().unwrap().http_requests.push(request);
+
+    let response = response.to_string();
+    let mut stream = stream;
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status, response.len(), response
+    )?;
+    stream.flush()
+}
+
+fn read_chunked(reader: &mut impl BufRead) -> io::Result<Vec<u8>> {
+    let mut body = Vec::new();
+    loop {
+        let mut size_line = String::new();
+        reader.read_line(&mut size_line)?;
+        let size = usize::from_str_radix(size_line.trim().split(';').next().unwrap_or("0"), 16)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let mut chunk = vec![0; size + 2];
+        reader.read_exact(&mut chunk)?;
+        if size == 0 {
+            return Ok(body);
+        }
+        body.extend_from_slice(&chunk[..size]);
+    }
+}
+
+fn parse_query(query: &str) -> HashMap<String, String> {
+    url::form_urlencoded::parse(query.as_bytes()).into_owned().collect()
+}
+
+fn route(request: &RecordedRequest, state: &Mutex<MockState>, upload_base: &str) -> (&'static str, Value) {
+    let mut state = state.lock().unwrap();
+    let ok = "200 OK";
+    match (request.method.as_str(), request.path.as_str()) {
+        ("POST", "/auth/sign_in") => (ok, json!({
+            "data": {"AuthenticationResult": {"AccessToken": "mock-access-token", "RefreshToken": "mock-refresh-token"}}
+        })),
+        ("GET", "/user/info") => (ok, json!({"data": {"user_id": "mock-user"}})),
+        ("POST", "/service/character/create") => {
+            let context = request.body["context"].clone();
+            (ok, json!({"data": {"character_id": state.next_id("character"), "character_context": context}}))
+        }
+        ("GET", "/file/upload") => {
+            let extension = request.query.get("extension").cloned().unwrap_or_default();
+            let key = format!("{}.{}", state.next_id("file"), extension);
+            (ok, json!({"data": {"url": format!("{}/upload/", upload_base), "fields": {"key": key}}}))
+        }
+        ("POST", "/upload/") => (ok, Value::Null),
+        ("GET", "/channel/character_list") => {
+            let channel_id = request.query.get("channel_id").cloned().unwrap_or_default();
+            let characters = state.characters.get(&channel_id).cloned().unwrap_or_default();
+            (ok, json!({"data": {"character_list": characters}}))
+        }
+        ("POST", "/service/group/create") => (ok, json!({"message": "Create success", "data": state.next_id("group")})),
+        ("POST", "/channel/group/create") => (ok, json!({"status": "success", "data": state.next_id("channel-group")})),
+        _ => ("404 Not Found", json!({"message": "Not found"})),
+    }
+}
+
+fn serve_ws(stream: TcpStream, state: &Mutex<MockState>) {
+    let mut socket = match tungstenite::accept(stream) {
+        Ok(socket) => socket,
+        Err(e) => {
+            println!("Mock WebSocket handshake failed: {}", e);
+            return;
+        }
+    };
+    // Only poll with a short timeout once connected, so a slow handshake is not cut off.
+    if socket.get_ref().set_read_timeout(Some(Duration::from_millis(10))).is_err() {
+        return;
+    }
+    loop {
+        let pending: Vec<Value> = state.lock().unwrap().ws_pending.drain(..).collect();
+        for payload in pending {
+            if socket.write_message(Message::Text(payload.to_string())).is_err() {
+                return;
+            }
+        }
+        match socket.read_message() {
+            Ok(Message::Text(text)) => {
+                let value = serde_json::from_str(&text).unwrap_or(Value::String(text));
+                state.lock().unwrap().ws_received.push(value);
+            }
+            Ok(Message::Binary(data)) => {
+                state.lock().unwrap().ws_received.push(json!({"binary": data}));
+            }
+            Ok(Message::Close(_)) => return,
+            Ok(_) => {}
+            Err(tungstenite::Error::Io(ref e)) if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut => {
+                // Nothing to read; flush anything buffered by write_message.
+                if socket.write_pending().is_err() {
+                    return;
+                }
+            }
+            Err(_) => return,
+        }
+    }
+}
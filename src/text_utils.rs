@@ -0,0 +1,130 @@
+/// How `Moobius::send_text_message` treats text longer than a limit.
+/// Limits are counted in characters, not bytes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LengthLimit {
+    /// Send everything as a single message.
+    Unlimited,
+    /// Cut the text down to this many characters.
+    Truncate(usize),
+    /// Send the text as several ordered messages of at most this many characters each.
+    Split(usize),
+}
+
+impl LengthLimit {
+    /// The messages to send for `text`. Fails for a limit of zero, which would leave nothing to send.
+    pub fn apply(&self, text: &str) -> Result<Vec<String>, String> {
+        match *self {
+            LengthLimit::Truncate(0) | LengthLimit::Split(0) => Err(format!("{:?} allows no characters", self)),
+            LengthLimit::Unlimited => Ok(vec![text.to_string()]),
+            LengthLimit::Truncate(len) => Ok(vec![truncate_chars(text, len)]),
+            LengthLimit::Split(len) => Ok(split_text(text, len)),
+        }
+    }
+}
+
+/// Truncates `text` to at most `len` characters without splitting a UTF-8 sequence.
+pub fn truncate_chars(text: &str, len: usize) -> String {
+    match text.char_indices().nth(len) {
+        Some((byte_idx, _)) => text[..byte_idx].to_string(),
+        None => text.to_string(),
+    }
+}
+
+/// Splits `text` into chunks of at most `max_chars` characters, preferring to break
+/// on paragraph boundaries, then sentence ends, then whitespace.
+/// Chunks are returned in order and never cut through a character.
+pub fn split_text(text: &str, max_chars: usize) -> Vec<String> {
+    if max_chars == 0 {
+        return vec![];
+    }
+    let mut chunks = Vec::new();
+    let mut rest = text;
+    while rest.chars().count() > max_chars {
+        // Byte offset just past the last character that still fits.
+        let limit = rest.char_indices().nth(max_chars).map(|(i, _)| i).unwrap_or(rest.len());
+        let window = &rest[..limit];
+        let cut = find_break(window).unwrap_or(limit);
+        let (head, tail) = rest.split_at(cut);
+        let head = head.trim_end();
+        if !head.is_empty() {
+            chunks.push(head.to_string());
+        }
+        rest = tail.trim_start();
+    }
+    if !rest.is_empty() {
+        chunks.push(rest.to_string());
+    }
+    chunks
+}
+
+fn find_break(window: &str) -> Option<usize> {
+    if let Some(i) = window.rfind("\n\n") {
+        if i > 0 {
+            return Some(i + 2);
+        }
+    }
+    let sentence_end = window
+        .char_indices()
+        .filter(|&(i, c)| match c {
+            '。' | '！' | '？' => true,
+            '.' | '!' | '?' => window[i + c.len_utf8()..].chars().next().is_some_and(char::is_whitespace),
+            _ => false,
+        })
+        .map(|(i, c)| i + c.len_utf8())
+        .next_back();
+    if let Some(i) = sentence_end {
+        return Some(i);
+    }
+    window
+        .char_indices()
+        .filter(|&(i, c)| c.is_whitespace() && i > 0)
+        .map(|(i, _)| i)
+        .next_back()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn truncate_keeps_multibyte_characters_whole() {
+        assert_eq!(truncate_chars("héllo wörld", 4), "héll");
+        assert_eq!(truncate_chars("👋🌍🎉", 2), "👋🌍");
+        assert_eq!(truncate_chars("你好世界", 3), "你好世");
+        assert_eq!(truncate_chars("short", 10), "short");
+    }
+
+    #[test]
+    fn split_never_exceeds_the_limit_in_characters() {
+        let text = "🎉".repeat(25);
+        let chunks = split_text(&text, 10);
+        assert_eq!(chunks.len(), 3);
+        assert!(chunks.iter().all(|c| c.chars().count() <= 10));
+        assert_eq!(chunks.concat(), text);
+    }
+
+    #[test]
+    fn split_prefers_paragraph_boundaries() {
+        let chunks = split_text("First paragraph.\n\nSecond one here.", 25);
+        assert_eq!(chunks, vec!["First paragraph.", "Second one here."]);
+    }
+
+    #[test]
+    fn split_prefers_sentence_ends() {
+        let chunks = split_text("One two. Three four five six", 15);
+        assert_eq!(chunks, vec!["One two.", "Three four", "five six"]);
+    }
+
+    #[test]
+    fn split_breaks_after_cjk_sentence_ends() {
+        let chunks = split_text("你好。世界很大", 5);
+        assert_eq!(chunks, vec!["你好。", "世界很大"]);
+    }
+
+    #[test]
+    fn zero_limit_is_an_error() {
+        assert!(LengthLimit::Split(0).apply("text").is_err());
+        assert!(LengthLimit::Truncate(0).apply("text").is_err());
+        assert_eq!(LengthLimit::Split(4).apply("ab cd").unwrap(), vec!["ab", "cd"]);
+    }
+}
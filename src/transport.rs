@@ -0,0 +1,190 @@
+use failure::{err_msg, Error};
+use futures::Stream as _;
+use serde_derive::{Serialize, Deserialize};
+use futures3::channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
+use futures3::compat::{Future01CompatExt, Sink01CompatExt, Stream01CompatExt};
+use futures3::{Sink, SinkExt, Stream, StreamExt};
+use std::future::Future;
+use std::pin::Pin;
+use tokio_tungstenite::connect_async;
+use tungstenite::error::Error as WsError;
+use tungstenite::Message;
+use url::Url;
+
+/// A WebSocket frame as seen by the SDK. Pings and pongs are handled by the transport.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum Frame {
+    Text(String),
+    Binary(Vec<u8>),
+    Close,
+}
+
+pub type TransportFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T, Error>> + Send + 'a>>;
+
+/// Moves frames to and from the server. `WebSocket` only talks to its transport,
+/// so the network can be replaced, e.g. by an `InMemoryTransport` in tests.
+pub trait Transport: Send {
+    /// Opens a connection to `url`.
+    fn connect(url: &str) -> TransportFuture<'static, Self>
+    where
+        Self: Sized;
+
+    fn send_frame(&mut self, frame: Frame) -> TransportFuture<'_, ()>;
+
+    /// Waits for the next frame. Returns `None` once the connection has ended.
+    /// Dropping the future before it completes must not lose a frame.
+    fn recv_frame(&mut self) -> TransportFuture<'_, Option<Frame>>;
+
+    fn close(&mut self) -> TransportFuture<'_, ()>;
+}
+
+/// A transport over a real WebSocket connection.
+///
+/// Frames are always sent uncompressed: tungstenite 0.8 cannot negotiate
+/// permessage-deflate and rejects incoming frames with the RSV1 bit set, which
+/// compressed frames carry.
+pub struct TungsteniteTransport {
+    sink: Pin<Box<dyn Sink<Message, Error = WsError> + Send>>,
+    stream: Pin<Box<dyn Stream<Item = Result<Message, WsError>> + Send>>,
+}
+
+impl Transport for TungsteniteTransport {
+    fn connect(url: &str) -> TransportFuture<'static, Self> {
+        let url = url.to_string();
+        Box::pin(async move {
+            let url = Url::parse(&url)?;
+            let (ws_stream, _) = connect_async(url).compat().await?;
+            let (sink, stream) = ws_stream.split();
+            let (sink, stream) = (sink.sink_compat(), stream.compat());
+            Ok(TungsteniteTransport { sink: Box::pin(sink), stream: Box::pin(stream) })
+        })
+    }
+
+    fn send_frame(&mut self, frame: Frame) -> TransportFuture<'_, ()> {
+        let msg = match frame {
+            Frame::Text(text) => Message::Text(text),
+            Frame::Binary(data) => Message::Binary(data),
+            Frame::Close => Message::Close(None),
+        };
+        Box::pin(async move {
+            self.sink.send(msg).await?;
+            Ok(())
+        })
+    }
+
+    fn recv_frame(&mut self) -> TransportFuture<'_, Option<Frame>> {
+        Box::pin(async move {
+            loop {
+                let msg = match self.stream.next().await {
+                    Some(msg) => msg?,
+                    None => return Ok(None),
+                };
+                match msg {
+                    Message::Text(text) => return Ok(Some(Frame::Text(text))),
+                    Message::Binary(data) => return Ok(Some(Frame::Binary(data))),
+                    Message::Ping(_) | Message::Pong(_) => {}
+                    Message::Close(_) => return Ok(Some(Frame::Close)),
+                }
+            }
+        })
+    }
+
+    fn close(&mut self) -> TransportFuture<'_, ()> {
+        Box::pin(async move {
+            self.sink.send(Message::Close(None)).await?;
+            self.sink.close().await?;
+            Ok(())
+        })
+    }
+}
+
+/// One end of an in-process duplex channel: frames sent on one end are received on the other.
+pub struct InMemoryTransport {
+    tx: UnboundedSender<Frame>,
+    rx: UnboundedReceiver<Frame>,
+}
+
+impl InMemoryTransport {
+    /// Returns two connected ends, e.g. one for a `WebSocket` and one for the test acting as server.
+    pub fn pair() -> (Self, Self) {
+        let (a_tx, b_rx) = unbounded();
+        let (b_tx, a_rx) = unbounded();
+        (InMemoryTransport { tx: a_tx, rx: a_rx }, InMemoryTransport { tx: b_tx, rx: b_rx })
+    }
+}
+
+impl Transport for InMemoryTransport {
+    /// In-memory transports have no address; create them with `InMemoryTransport::pair`.
+    fn connect(url: &str) -> TransportFuture<'static, Self> {
+        let message = format!("cannot connect an in-memory transport to {}; use InMemoryTransport::pair", url);
+        Box::pin(async move { Err(err_msg(message)) })
+    }
+
+    fn send_frame(&mut self, frame: Frame) -> TransportFuture<'_, ()> {
+        let sent = self.tx.unbounded_send(frame).map_err(|_| err_msg("in-memory transport peer is closed"));
+        Box::pin(async move { sent })
+    }
+
+    fn recv_frame(&mut self) -> TransportFuture<'_, Option<Frame>> {
+        Box::pin(async move { Ok(self.rx.next().await) })
+    }
+
+    fn close(&mut self) -> TransportFuture<'_, ()> {
+        let _ = self.tx.unbounded_send(Frame::Close);
+        self.tx.close_channel();
+        Box::pin(async move { Ok(()) })
+    }
+}
+
+/// Hands sent frames to a channel, e.g. for the listen loop to send on the real
+/// connection. Never receives anything.
+pub(crate) struct ForwardTransport {
+    tx: tokio::sync::mpsc::UnboundedSender<Frame>,
+}
+
+impl ForwardTransport {
+    pub(crate) fn new(tx: tokio::sync::mpsc::UnboundedSender<Frame>) -> Self {
+        ForwardTransport { tx }
+    }
+}
+
+impl Transport for ForwardTransport {
+    fn connect(url: &str) -> TransportFuture<'static, Self> {
+        let message = format!("cannot connect a forwarding transport to {}", url);
+        Box::pin(async move { Err(err_msg(message)) })
+    }
+
+    fn send_frame(&mut self, frame: Frame) -> TransportFuture<'_, ()> {
+        let sent = self.tx.send(frame).map_err(|_| err_msg("connection is no longer being served"));
+        Box::pin(async move { sent })
+    }
+
+    fn recv_frame(&mut self) -> TransportFuture<'_, Option<Frame>> {
+        Box::pin(async move { Ok(None) })
+    }
+
+    fn close(&mut self) -> TransportFuture<'_, ()> {
+        Box::pin(async move { Ok(()) })
+    }
+}
+
+/// Discards everything sent and never receives anything.
+pub(crate) struct NullTransport;
+
+impl Transport for NullTransport {
+    fn connect(_url: &str) -> TransportFuture<'static, Self> {
+        Box::pin(async move { Ok(NullTransport) })
+    }
+
+    fn send_frame(&mut self, _frame: Frame) -> TransportFuture<'_, ()> {
+        Box::pin(async move { Ok(()) })
+    }
+
+    fn recv_frame(&mut self) -> TransportFuture<'_, Option<Frame>> {
+        Box::pin(async move { Ok(None) })
+    }
+
+    fn close(&mut self) -> TransportFuture<'_, ()> {
+        Box::pin(async move { Ok(()) })
+    }
+}
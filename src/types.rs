@@ -1,5 +1,9 @@
 use serde_derive::{Serialize, Deserialize};
 use serde_json::{Value, json, Map};
+use crate::socket::{ProtocolKind};
+use crate::outbound::{OutboundConfig};
+use crate::outbox::{OutboxConfig};
+use crate::dispatch::{ConcurrencyConfig};
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Config {
@@ -9,9 +13,33 @@ pub struct Config {
     pub password: String,
     pub service_id: Option<String>,
     pub channels: Vec<String>,
+    /// JSON file with the buttons sent on `fetch_buttons`. Can be replaced with `Moobius::set_button_source`.
+    #[serde(default)]
+    pub buttons_path: Option<String>,
+    /// Re-read `buttons_path` whenever it changes on disk.
+    #[serde(default)]
+    pub buttons_hot_reload: bool,
+    /// Encoding of WebSocket messages.
+    #[serde(default)]
+    pub protocol: ProtocolKind,
+    /// Rate limit and queueing of outbound WebSocket messages; unpaced if not set.
+    #[serde(default)]
+    pub outbound: Option<OutboundConfig>,
+    /// Buffer messages while the WebSocket is down instead of failing the send.
+    #[serde(default)]
+    pub outbox: Option<OutboxConfig>,
+    /// Limits and ordering for handlers registered on `Moobius::dispatcher`.
+    #[serde(default)]
+    pub concurrency: ConcurrencyConfig,
+    /// File the database is loaded from at start and saved to on shutdown; in memory only if not set.
+    #[serde(default)]
+    pub db_path: Option<String>,
+    /// How long shutdown waits for running handlers; 30 seconds if not set.
+    #[serde(default)]
+    pub shutdown_timeout_secs: Option<u64>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Character {
     pub character_id: String,
     pub name: String,
@@ -20,11 +48,172 @@ pub struct Character {
     pub character_context: Map<String, Value>, // Using a HashMap to represent arbitrary JSON data
 }
 
-#[derive(Debug)]
-pub struct MessageContent {
-    pub filename: String,
-    pub size: Option<u64>,
-    pub path: String,
-    pub text: Option<String>,
+/// Who a message is addressed to.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Recipients {
+    /// A list of character IDs, converted to a service group on send.
+    Characters(Vec<String>),
+    /// An existing group ID.
+    Group(String),
+    /// Every real character currently in the channel.
+    Channel,
+}
+
+impl From<Vec<String>> for Recipients {
+    fn from(character_ids: Vec<String>) -> Self {
+        Recipients::Characters(character_ids)
+    }
+}
+
+/// A message_down that was handed to the WebSocket.
+#[derive(Debug, Clone)]
+pub struct SentMessage {
+    pub request_id: String,
+    pub channel_id: String,
+    pub group_id: String,
+    pub message: Value,
+}
+
+impl SentMessage {
+    pub(crate) fn new(channel_id: &str, group_id: &str, message: Value) -> Result<Self, Box<dyn std::error::Error>> {
+        let request_id = message["request_id"].as_str().ok_or("Sent message has no request_id")?.to_string();
+        Ok(SentMessage {
+            request_id,
+            channel_id: channel_id.to_string(),
+            group_id: group_id.to_string(),
+            message,
+        })
+    }
 }
 
+/// The content of a message, one variant per message subtype.
+/// Serializes to the `content` object the Moobius server expects for that subtype;
+/// use `from_body` to parse received content, since the subtype lives outside it.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum MessageContent {
+    Text {
+        text: String,
+    },
+    Image {
+        path: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        width: Option<u32>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        height: Option<u32>,
+    },
+    File {
+        path: String,
+        filename: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        size: Option<u64>,
+    },
+    Audio {
+        path: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        duration: Option<f64>,
+    },
+    Card {
+        title: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        text: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        image: Option<String>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        links: Vec<CardLink>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CardLink {
+    pub text: String,
+    pub url: String,
+}
+
+impl MessageContent {
+    pub fn text(text: impl Into<String>) -> Self {
+        MessageContent::Text { text: text.into() }
+    }
+
+    pub fn image(path: impl Into<String>) -> Self {
+        MessageContent::Image { path: path.into(), width: None, height: None }
+    }
+
+    pub fn image_with_size(path: impl Into<String>, width: u32, height: u32) -> Self {
+        MessageContent::Image { path: path.into(), width: Some(width), height: Some(height) }
+    }
+
+    pub fn file(path: impl Into<String>, filename: impl Into<String>, size: Option<u64>) -> Self {
+        MessageContent::File { path: path.into(), filename: filename.into(), size }
+    }
+
+    pub fn audio(path: impl Into<String>) -> Self {
+        MessageContent::Audio { path: path.into(), duration: None }
+    }
+
+    pub fn card(title: impl Into<String>) -> Self {
+        MessageContent::Card { title: title.into(), text: None, image: None, links: Vec::new() }
+    }
+
+    /// The `subtype` field that accompanies this content in a message body.
+    pub fn subtype(&self) -> &'static str {
+        match self {
+            MessageContent::Text { .. } => "text",
+            MessageContent::Image { .. } => "image",
+            MessageContent::File { .. } => "file",
+            MessageContent::Audio { .. } => "audio",
+            MessageContent::Card { .. } => "card",
+        }
+    }
+
+    /// Parses the `content` of a received message body according to its `subtype`.
+    pub fn from_body(subtype: &str, content: &Value) -> Option<Self> {
+        let parsed = match subtype {
+            "text" => MessageContent::Text { text: content["text"].as_str()?.to_string() },
+            "image" => MessageContent::Image {
+                path: content["path"].as_str()?.to_string(),
+                width: content["width"].as_u64().map(|w| w as u32),
+                height: content["height"].as_u64().map(|h| h as u32),
+            },
+            "file" => MessageContent::File {
+                path: content["path"].as_str()?.to_string(),
+                filename: content["filename"].as_str().unwrap_or_default().to_string(),
+                size: content["size"].as_u64(),
+            },
+            "audio" => MessageContent::Audio {
+                path: content["path"].as_str()?.to_string(),
+                duration: content["duration"].as_f64(),
+            },
+            "card" => serde_json::from_value::<CardContent>(content.clone()).ok()?.into(),
+            _ => return None,
+        };
+        Some(parsed)
+    }
+
+    pub fn to_value(&self) -> Value {
+        serde_json::to_value(self).unwrap_or_else(|_| json!({}))
+    }
+
+    /// Returns the text of a `Text` message, if this is one.
+    pub fn as_text(&self) -> Option<&str> {
+        match self {
+            MessageContent::Text { text } => Some(text),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct CardContent {
+    title: String,
+    text: Option<String>,
+    image: Option<String>,
+    #[serde(default)]
+    links: Vec<CardLink>,
+}
+
+impl From<CardContent> for MessageContent {
+    fn from(card: CardContent) -> Self {
+        MessageContent::Card { title: card.title, text: card.text, image: card.image, links: card.links }
+    }
+}
@@ -0,0 +1,89 @@
+use moobius::{Config, FakeHttpApi, Frame, InMemoryTransport, MessageContent, Moobius, Recipients, Transport};
+use serde_json::{json, Value};
+
+fn config() -> Config {
+    serde_json::from_value(json!({
+        "http_server_uri": "http://localhost",
+        "ws_server_uri": "ws://localhost",
+        "email": "",
+        "password": "",
+        "service_id": "service-1",
+        "channels": ["channel-1"],
+    })).unwrap()
+}
+
+async fn next_message(server: &mut InMemoryTransport) -> Value {
+    match server.recv_frame().await.unwrap() {
+        Some(Frame::Text(text)) => serde_json::from_str(&text).unwrap(),
+        frame => panic!("expected a text frame, got {:?}", frame),
+    }
+}
+
+#[tokio::test]
+async fn task_handler_uploads_and_sends_to_characters() {
+    let (client, mut server) = InMemoryTransport::pair();
+    let http = FakeHttpApi::new();
+    let mut moobius = Moobius::with_transport(config(), client).unwrap().with_http_api(http.clone());
+    moobius.dispatcher.on("message_up", |mut ctx| Box::pin(async move {
+        let channel_id = ctx.body()["channel_id"].as_str().unwrap_or_default().to_string();
+        let sender = ctx.body()["sender"].as_str().unwrap_or_default().to_string();
+        ctx.send_image_message("cat.png", &channel_id, "service-1", Recipients::Characters(vec![sender])).await?;
+        Ok(())
+    }));
+
+    let shutdown = moobius.shutdown_handle();
+    let server_side = async move {
+        let message_up = json!({
+            "type": "message_up",
+            "body": {"subtype": "text", "channel_id": "channel-1", "sender": "user-1", "content": {"text": "cat please"}}
+        });
+        server.send_frame(Frame::Text(message_up.to_string())).await.unwrap();
+        let reply = next_message(&mut server).await;
+        shutdown.shutdown();
+        // Keep the connection open until listen has stopped.
+        (reply, server)
+    };
+    let (listened, (reply, _server)) = tokio::join!(moobius.listen(), server_side);
+    listened.unwrap();
+
+    assert_eq!(reply["type"], "message_down");
+    assert_eq!(reply["body"]["subtype"], "image");
+    assert_eq!(reply["body"]["channel_id"], "channel-1");
+    assert_eq!(http.uploads(), vec!["cat.png".to_string()]);
+    let recipients = reply["body"]["recipients"].as_str().unwrap();
+    assert_eq!(http.groups()[recipients], vec!["user-1".to_string()]);
+}
+
+#[tokio::test]
+async fn panicking_task_handler_does_not_stall_later_payloads() {
+    let (client, mut server) = InMemoryTransport::pair();
+    let mut moobius = Moobius::with_transport(config(), client).unwrap().with_http_api(FakeHttpApi::new());
+    moobius.dispatcher.on("message_up", |mut ctx| Box::pin(async move {
+        let text = ctx.body()["content"]["text"].as_str().unwrap_or_default().to_string();
+        if text == "panic" {
+            panic!("handler failed");
+        }
+        let sender = ctx.body()["sender"].as_str().unwrap_or_default().to_string();
+        ctx.send_message(&MessageContent::text(text), "channel-1", "service-1", Recipients::Characters(vec![sender])).await?;
+        Ok(())
+    }));
+
+    let shutdown = moobius.shutdown_handle();
+    let server_side = async move {
+        for text in &["panic", "still here"] {
+            let message_up = json!({
+                "type": "message_up",
+                "body": {"subtype": "text", "channel_id": "channel-1", "sender": "user-1", "content": {"text": text}}
+            });
+            server.send_frame(Frame::Text(message_up.to_string())).await.unwrap();
+        }
+        let reply = next_message(&mut server).await;
+        shutdown.shutdown();
+        (reply, server)
+    };
+    let (listened, (reply, _server)) = tokio::join!(moobius.listen(), server_side);
+    listened.unwrap();
+
+    assert_eq!(reply["body"]["content"]["text"], "still here");
+    assert_eq!(moobius.dispatcher.in_flight(), 0);
+}
@@ -0,0 +1,83 @@
+use moobius::{Button, ButtonSource, Command, Config, FakeHttpApi, Frame, InMemoryTransport, MessageContent, Moobius, Transport};
+use serde_json::{json, Value};
+
+fn config() -> Config {
+    serde_json::from_value(json!({
+        "http_server_uri": "http://localhost",
+        "ws_server_uri": "ws://localhost",
+        "email": "",
+        "password": "",
+        "service_id": "service-1",
+        "channels": ["channel-1"],
+    })).unwrap()
+}
+
+async fn next_message(server: &mut InMemoryTransport) -> Value {
+    match server.recv_frame().await.unwrap() {
+        Some(Frame::Text(text)) => serde_json::from_str(&text).unwrap(),
+        frame => panic!("expected a text frame, got {:?}", frame),
+    }
+}
+
+#[tokio::test]
+async fn fetch_buttons_is_answered_over_the_transport() {
+    let (client, mut server) = InMemoryTransport::pair();
+    let http = FakeHttpApi::new();
+    let mut moobius = Moobius::with_transport(config(), client).unwrap().with_http_api(http.clone());
+    moobius.set_button_source(ButtonSource::Static(vec![Button::new("hello", "Hello", "Hello!")]));
+
+    let shutdown = moobius.shutdown_handle();
+    let server_side = async move {
+        let fetch_buttons = json!({
+            "type": "action",
+            "body": {"subtype": "fetch_buttons", "channel_id": "channel-1", "sender": "user-1"}
+        });
+        server.send_frame(Frame::Text(fetch_buttons.to_string())).await.unwrap();
+        let reply = next_message(&mut server).await;
+        shutdown.shutdown();
+        // Keep the connection open until listen has stopped.
+        (reply, server)
+    };
+    let (listened, (reply, _server)) = tokio::join!(moobius.listen(), server_side);
+    listened.unwrap();
+
+    assert_eq!(reply["type"], "update");
+    assert_eq!(reply["service_id"], "service-1");
+    assert_eq!(reply["body"]["subtype"], "update_buttons");
+    assert_eq!(reply["body"]["channel_id"], "channel-1");
+    assert_eq!(reply["body"]["content"], json!([{"button_id": "hello", "button_name": "Hello", "button_text": "Hello!", "new_window": false}]));
+    let recipients = reply["body"]["recipients"].as_str().unwrap();
+    assert_eq!(http.groups()[recipients], vec!["user-1".to_string()]);
+}
+
+#[tokio::test]
+async fn broadcast_reaches_the_channel_members_of_the_fake_http_api() {
+    let (client, mut server) = InMemoryTransport::pair();
+    let members = vec!["user-1".to_string(), "user-2".to_string()];
+    let http = FakeHttpApi::new().with_channel_characters("channel-1", members.clone());
+    let mut moobius = Moobius::with_transport(config(), client).unwrap().with_http_api(http.clone());
+    moobius.commands.register(Command::new("announce", "Tell everyone.", |moobius, cmd| Box::pin(async move {
+        moobius.broadcast(&cmd.channel_id, &MessageContent::text(cmd.args.join(" "))).await?;
+        Ok(())
+    })));
+
+    let shutdown = moobius.shutdown_handle();
+    let server_side = async move {
+        let message_up = json!({
+            "type": "message_up",
+            "body": {"subtype": "text", "channel_id": "channel-1", "sender": "user-1", "content": {"text": "announce lunch is ready"}}
+        });
+        server.send_frame(Frame::Text(message_up.to_string())).await.unwrap();
+        let reply = next_message(&mut server).await;
+        shutdown.shutdown();
+        (reply, server)
+    };
+    let (listened, (reply, _server)) = tokio::join!(moobius.listen(), server_side);
+    listened.unwrap();
+
+    assert_eq!(reply["type"], "message_down");
+    assert_eq!(reply["body"]["sender"], "service-1");
+    assert_eq!(reply["body"]["content"]["text"], "lunch is ready");
+    let recipients = reply["body"]["recipients"].as_str().unwrap();
+    assert_eq!(http.groups()[recipients], members);
+}
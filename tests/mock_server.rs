@@ -0,0 +1,35 @@
+#![cfg(feature = "testing")]
+
+use moobius::testing::MockServer;
+use moobius::{Button, ButtonSource, Moobius};
+use serde_json::json;
+use std::sync::Arc;
+use std::time::Duration;
+
+#[tokio::test]
+async fn fetch_buttons_replies_with_update_buttons() {
+    let server = Arc::new(MockServer::start().unwrap());
+    let mut moobius = Moobius::new(server.config("service-1", vec!["channel-1".to_string()])).await.unwrap();
+    moobius.set_button_source(ButtonSource::Static(vec![Button::new("hello_btn", "hello_btn", "hello_btn")]));
+
+    server.inject(json!({
+        "type": "action",
+        "body": {"subtype": "fetch_buttons", "channel_id": "channel-1", "sender": "user-1"}
+    }));
+    let shutdown = moobius.shutdown_handle();
+    let waiting = Arc::clone(&server);
+    let wait_for_update = async move {
+        let update = tokio::task::spawn_blocking(move || {
+            waiting.wait_for_message(|m| m["body"]["subtype"] == "update_buttons", Duration::from_secs(5))
+        }).await.unwrap();
+        shutdown.shutdown();
+        update
+    };
+    let (listened, update) = tokio::join!(moobius.listen(), wait_for_update);
+    listened.unwrap();
+
+    let update = update.expect("no update_buttons received");
+    assert_eq!(update["service_id"], "service-1");
+    assert_eq!(update["body"]["channel_id"], "channel-1");
+    assert_eq!(update["body"]["content"][0]["button_id"], "hello_btn");
+}
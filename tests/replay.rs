@@ -0,0 +1,65 @@
+use moobius::{read_recording, Button, ButtonSource, Config, Direction, FakeHttpApi, Frame, InMemoryTransport, Moobius, Transport};
+use serde_json::{json, Value};
+
+fn config() -> Config {
+    serde_json::from_value(json!({
+        "http_server_uri": "http://localhost",
+        "ws_server_uri": "ws://localhost",
+        "email": "",
+        "password": "",
+        "service_id": "service-1",
+        "channels": ["channel-1"],
+    })).unwrap()
+}
+
+fn temp_path(name: &str) -> String {
+    let path = std::env::temp_dir().join(format!("moobius-{}-{}.jsonl", name, std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    path.to_str().unwrap().to_string()
+}
+
+fn outbound(path: &str) -> Vec<Value> {
+    read_recording(path).unwrap().into_iter()
+        .filter(|recorded| recorded.direction == Direction::Out)
+        .map(|recorded| recorded.message)
+        .collect()
+}
+
+#[tokio::test]
+async fn replaying_a_recording_offline_sends_the_same_replies() {
+    let recording = temp_path("recording");
+    let (client, mut server) = InMemoryTransport::pair();
+    let mut live = Moobius::with_transport(config(), client).unwrap().with_http_api(FakeHttpApi::new());
+    live.set_button_source(ButtonSource::Static(vec![Button::new("hello_btn", "Hello", "Say hello")]));
+    live.record_to(&recording).unwrap();
+
+    let shutdown = live.shutdown_handle();
+    let server_side = async move {
+        let fetch_buttons = json!({
+            "type": "action",
+            "body": {"subtype": "fetch_buttons", "channel_id": "channel-1", "sender": "user-1"}
+        });
+        server.send_frame(Frame::Text(fetch_buttons.to_string())).await.unwrap();
+        let reply = server.recv_frame().await.unwrap();
+        shutdown.shutdown();
+        (reply, server)
+    };
+    let (listened, (reply, _server)) = tokio::join!(live.listen(), server_side);
+    listened.unwrap();
+    assert!(reply.is_some());
+
+    let replayed = temp_path("replayed");
+    let mut offline = Moobius::offline(config()).unwrap();
+    offline.set_button_source(ButtonSource::Static(vec![Button::new("hello_btn", "Hello", "Say hello")]));
+    offline.record_to(&replayed).unwrap();
+    offline.replay(&recording).await.unwrap();
+
+    let (live_sent, replay_sent) = (outbound(&recording), outbound(&replayed));
+    let _ = std::fs::remove_file(&recording);
+    let _ = std::fs::remove_file(&replayed);
+    assert_eq!(live_sent.len(), 1);
+    assert_eq!(replay_sent.len(), 1);
+    // Request IDs are fresh on every send; everything else must match.
+    assert_eq!(replay_sent[0]["body"], live_sent[0]["body"]);
+    assert_eq!(replay_sent[0]["body"]["subtype"], "update_buttons");
+}
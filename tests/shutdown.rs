@@ -0,0 +1,39 @@
+use moobius::{Config, FakeHttpApi, InMemoryTransport, Moobius, MoobiusDatabase, OutboundConfig, OverflowPolicy};
+use serde_json::{json, Value};
+use std::time::{Duration, Instant};
+
+fn config() -> Config {
+    serde_json::from_value(json!({
+        "http_server_uri": "http://localhost",
+        "ws_server_uri": "ws://localhost",
+        "email": "",
+        "password": "",
+        "service_id": "service-1",
+        "channels": ["channel-1"],
+    })).unwrap()
+}
+
+#[tokio::test]
+async fn shutdown_saves_database_within_timeout_despite_queued_messages() {
+    let path = std::env::temp_dir().join(format!("moobius-shutdown-{}.json", std::process::id()));
+    let path = path.to_str().unwrap().to_string();
+    let mut config = config();
+    config.db_path = Some(path.clone());
+    // One message every 100 seconds: everything after the first stays queued.
+    config.outbound = Some(OutboundConfig { capacity: 10, rate_per_sec: 0.01, burst: 1, overflow: OverflowPolicy::Block });
+    let (client, _server) = InMemoryTransport::pair();
+    let mut moobius = Moobius::with_transport(config, client).unwrap().with_http_api(FakeHttpApi::new());
+    moobius.db.add_field("answer", json!(42));
+    for _ in 0..3 {
+        moobius.ws_client.send::<Value>(json!({"type": "update"})).await.unwrap();
+    }
+    assert_eq!(moobius.ws_client.outbound_queue().unwrap().len(), 2);
+
+    let started = Instant::now();
+    moobius.shutdown(Duration::from_millis(200)).await.unwrap();
+    assert!(started.elapsed() < Duration::from_secs(5));
+
+    let saved = MoobiusDatabase::load(&path).unwrap();
+    let _ = std::fs::remove_file(&path);
+    assert_eq!(saved.get_field("answer"), Some(&json!(42)));
+}